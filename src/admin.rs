@@ -0,0 +1,34 @@
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+
+use crate::app::AppState;
+
+pub async fn sequence_reports(State(st): State<AppState>) -> impl IntoResponse {
+    Json(st.seq.reports())
+}
+
+pub async fn sequence_report(
+    State(st): State<AppState>,
+    Path(device_id): Path<String>,
+) -> impl IntoResponse {
+    match st.seq.report(&device_id) {
+        Some(report) => Json(report).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub async fn clock_reports(State(st): State<AppState>) -> impl IntoResponse {
+    Json(st.clock.reports())
+}
+
+pub async fn clock_report(
+    State(st): State<AppState>,
+    Path(device_id): Path<String>,
+) -> impl IntoResponse {
+    match st.clock.report(&device_id) {
+        Some(report) => Json(report).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -1,15 +1,28 @@
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
+use crate::clock::ClockTracker;
 use crate::config::GatewayGfg;
 use crate::domain::Event;
+use crate::ingest::budget::ByteBudget;
+use crate::ingest::decoder::PayloadDecoders;
+use crate::ingest::prometheus::SeriesLimiter;
 use crate::metrics::AppMetrics;
 use crate::readiness::Readiness;
+use crate::registry::MetricRegistry;
+use crate::sequence::SeqTracker;
 
 #[derive(Clone)]
 pub struct AppState {
     pub cfg: Arc<GatewayGfg>,
     pub ready: Arc<Readiness>,
     pub ingest_tx: mpsc::Sender<Event>,
+    pub inflight: Arc<ByteBudget>,
     pub metrics: Arc<AppMetrics>,
+    pub seq: Arc<SeqTracker>,
+    pub clock: Arc<ClockTracker>,
+    /// Label sets seen per device on Prometheus pushes.
+    pub series: Arc<SeriesLimiter>,
+    pub decoders: Arc<PayloadDecoders>,
+    pub registry: Arc<MetricRegistry>,
 }
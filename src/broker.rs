@@ -0,0 +1,461 @@
+//! Minimal embedded MQTT 3.1.1 broker for sites without one. Devices connect
+//! directly and their publishes on the telemetry topic go into the ingest pipeline;
+//! nothing is routed back out, so subscriptions are refused.
+
+use bytes::BytesMut;
+use rumqttc::mqttbytes::{self, v4};
+use rumqttc::{ConnectReturnCode, Packet, QoS, SubscribeReasonCode};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+
+use crate::app::AppState;
+use crate::config::BrokerCfg;
+use crate::device_map::DeviceMap;
+use crate::mqtt_ingress::{self, TopicTemplate};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn serve(cfg: BrokerCfg, st: AppState) -> anyhow::Result<()> {
+    let ready = st.ready.clone();
+    let result = listen(cfg, st).await;
+    ready.broker_ok.store(false, Ordering::Relaxed);
+    result
+}
+
+async fn listen(cfg: BrokerCfg, st: AppState) -> anyhow::Result<()> {
+    let template = Arc::new(TopicTemplate::parse(&cfg.telemetry_topic)?);
+    let listener = TcpListener::bind(cfg.bind).await?;
+    tracing::info!(addr = %listener.local_addr()?, "mqtt broker listening");
+    st.ready.broker_ok.store(true, Ordering::Relaxed);
+    let cfg = Arc::new(cfg);
+    let sessions = Arc::new(Sessions::new(st.cfg.ingest.max_tracked_devices));
+
+    let stopped = st.ready.stopped();
+    tokio::pin!(stopped);
+    loop {
+        let accepted = tokio::select! {
+            r = listener.accept() => r,
+            _ = &mut stopped => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!(error = %e, "mqtt broker accept failed");
+                continue;
+            }
+        };
+        let (cfg, template, sessions, st) =
+            (cfg.clone(), template.clone(), sessions.clone(), st.clone());
+        tokio::spawn(async move {
+            if let Err(e) = session(stream, peer, &cfg, &template, &sessions, &st).await {
+                tracing::debug!(%peer, error = %e, "mqtt session closed");
+            }
+        });
+    }
+    tracing::info!("mqtt broker stopped");
+    Ok(())
+}
+
+/// Broker-side state of one client id.
+#[derive(Default)]
+struct ClientSession {
+    /// Notified when another connection takes the client id over.
+    kick: Arc<Notify>,
+    /// QoS 2 packet ids received but not yet released by PUBREL.
+    awaiting_rel: HashSet<u16>,
+}
+
+/// Sessions by client id. Persistent (`clean_session = false`) sessions keep
+/// their QoS 2 state across reconnects so a resent PUBLISH isn't ingested twice.
+struct Sessions(Mutex<DeviceMap<ClientSession>>);
+
+impl Sessions {
+    fn new(max_clients: usize) -> Self {
+        Self(Mutex::new(DeviceMap::new(max_clients)))
+    }
+
+    /// Registers a connection for `client_id`, taking over any live one
+    /// [MQTT-3.1.4-2]. Returns its takeover handle and whether a session was present.
+    fn open(&self, client_id: &str, clean: bool) -> (Arc<Notify>, bool) {
+        let mut sessions = self.0.lock().unwrap();
+        let present = !clean && sessions.contains_key(client_id);
+        let session = sessions.touch(client_id);
+        std::mem::take(&mut session.kick).notify_one();
+        if clean {
+            session.awaiting_rel.clear();
+        }
+        (session.kick.clone(), present)
+    }
+
+    /// Forgets the session unless it was taken over or has state to keep.
+    fn close(&self, client_id: &str, kick: &Arc<Notify>, clean: bool) {
+        let mut sessions = self.0.lock().unwrap();
+        if let Some(session) = sessions.get(client_id)
+            && Arc::ptr_eq(&session.kick, kick)
+            && (clean || session.awaiting_rel.is_empty())
+        {
+            sessions.remove(client_id);
+        }
+    }
+
+    fn awaiting_rel(&self, client_id: &str, pkid: u16) -> bool {
+        let sessions = self.0.lock().unwrap();
+        sessions
+            .get(client_id)
+            .is_some_and(|s| s.awaiting_rel.contains(&pkid))
+    }
+
+    fn set_awaiting_rel(&self, client_id: &str, pkid: u16, awaiting: bool) {
+        let mut sessions = self.0.lock().unwrap();
+        if let Some(session) = sessions.get_mut(client_id) {
+            if awaiting {
+                session.awaiting_rel.insert(pkid);
+            } else {
+                session.awaiting_rel.remove(&pkid);
+            }
+        }
+    }
+}
+
+/// Keeps the connection count in `Readiness` and `/metrics` in step with live sessions.
+struct ConnGuard<'a>(&'a AppState);
+
+impl<'a> ConnGuard<'a> {
+    /// `None` when `max` sessions are already live.
+    fn reserve(st: &'a AppState, max: usize) -> Option<Self> {
+        let n = take_slot(&st.ready.broker_clients, max)?;
+        st.metrics.broker_connections(n);
+        Some(Self(st))
+    }
+}
+
+/// Atomically bumps `clients` unless it is at `max`; returns the new count.
+fn take_slot(clients: &AtomicUsize, max: usize) -> Option<usize> {
+    clients
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+            (n < max).then_some(n + 1)
+        })
+        .ok()
+        .map(|n| n + 1)
+}
+
+impl Drop for ConnGuard<'_> {
+    fn drop(&mut self) {
+        let n = self.0.ready.broker_clients.fetch_sub(1, Ordering::Relaxed) - 1;
+        self.0.metrics.broker_connections(n);
+    }
+}
+
+async fn session(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    cfg: &BrokerCfg,
+    template: &TopicTemplate,
+    sessions: &Sessions,
+    st: &AppState,
+) -> anyhow::Result<()> {
+    let max_packet = st.cfg.ingest.max_payload_bytes + 1024;
+    let mut buf = BytesMut::with_capacity(4096);
+    let mut out = BytesMut::new();
+
+    let connect = match next_packet(&mut stream, &mut buf, max_packet, CONNECT_TIMEOUT).await {
+        Ok(Some(Packet::Connect(c))) => c,
+        Err(e)
+            if matches!(
+                e.downcast_ref(),
+                Some(mqttbytes::Error::InvalidProtocolLevel(_))
+            ) =>
+        {
+            refuse(&mut stream, ConnectReturnCode::RefusedProtocolVersion).await?;
+            return Err(e);
+        }
+        Ok(_) => anyhow::bail!("expected CONNECT"),
+        Err(e) => return Err(e),
+    };
+
+    let mut code = if !st.ready.accepting.load(Ordering::Relaxed) {
+        ConnectReturnCode::ServiceUnavailable
+    } else {
+        authenticate(cfg, &connect)
+    };
+    let guard = match code {
+        ConnectReturnCode::Success => ConnGuard::reserve(st, cfg.max_connections),
+        _ => None,
+    };
+    if guard.is_none() && code == ConnectReturnCode::Success {
+        code = ConnectReturnCode::ServiceUnavailable;
+    }
+    st.metrics.broker_connect(code);
+    let Some(_guard) = guard else {
+        tracing::info!(%peer, client_id = %connect.client_id, ?code, "mqtt connect refused");
+        return refuse(&mut stream, code).await;
+    };
+    let client_id = connect.client_id.as_str();
+    let (kick, present) = sessions.open(client_id, connect.clean_session);
+    v4::ConnAck::new(ConnectReturnCode::Success, present).write(&mut out)?;
+    let result = async {
+        flush(&mut stream, &mut out).await?;
+        tracing::info!(%peer, %client_id, "mqtt client connected");
+        let conn = Conn {
+            stream: &mut stream,
+            buf,
+            out,
+            max_packet,
+        };
+        serve_client(conn, &connect, cfg, template, sessions, &kick, st).await
+    }
+    .await;
+    sessions.close(client_id, &kick, connect.clean_session);
+    tracing::info!(%peer, %client_id, "mqtt client disconnected");
+    result
+}
+
+struct Conn<'s> {
+    stream: &'s mut TcpStream,
+    buf: BytesMut,
+    out: BytesMut,
+    max_packet: usize,
+}
+
+async fn serve_client(
+    mut conn: Conn<'_>,
+    connect: &v4::Connect,
+    cfg: &BrokerCfg,
+    template: &TopicTemplate,
+    sessions: &Sessions,
+    kick: &Notify,
+    st: &AppState,
+) -> anyhow::Result<()> {
+    let client_id = connect.client_id.as_str();
+    // [MQTT-3.1.2-24]: drop the client after 1.5x keep-alive without a packet.
+    let idle = match connect.keep_alive {
+        0 => Duration::MAX,
+        k => Duration::from_millis(u64::from(k) * 1500),
+    };
+    let kicked = kick.notified();
+    let stopped = st.ready.stopped();
+    tokio::pin!(kicked, stopped);
+    loop {
+        let next = next_packet(conn.stream, &mut conn.buf, conn.max_packet, idle);
+        let packet = tokio::select! {
+            p = next => p?,
+            _ = &mut kicked => anyhow::bail!("taken over by a new connection"),
+            _ = &mut stopped => return Ok(()),
+        };
+        let Some(packet) = packet else {
+            return Ok(());
+        };
+        let out = &mut conn.out;
+        match packet {
+            Packet::Publish(p) => {
+                // A resent QoS 2 PUBLISH whose PUBREL hasn't arrived was already ingested.
+                if p.qos == QoS::ExactlyOnce && sessions.awaiting_rel(client_id, p.pkid) {
+                    st.metrics.broker_message("duplicate");
+                    v4::PubRec::new(p.pkid).write(out)?;
+                    flush(conn.stream, out).await?;
+                    continue;
+                }
+                let result = match template.device_id(&p.topic) {
+                    None => "unmatched_topic",
+                    Some(id) if cfg.bind_device_to_client_id && id != client_id => "forbidden",
+                    Some(id) => {
+                        mqtt_ingress::submit_payload(st, id, &p.payload, cfg.backpressure_retries)
+                            .await
+                    }
+                };
+                st.metrics.broker_message(result);
+                match result {
+                    // Unacked, so the client resends once it has reconnected.
+                    "dropped" if p.qos != QoS::AtMostOnce => {
+                        anyhow::bail!("pipeline backed up, disconnecting")
+                    }
+                    "forbidden" => anyhow::bail!("publish for another device on {}", p.topic),
+                    _ => {}
+                }
+                match p.qos {
+                    QoS::AtMostOnce => {}
+                    QoS::AtLeastOnce => v4::PubAck::new(p.pkid).write(out).map(drop)?,
+                    QoS::ExactlyOnce => {
+                        sessions.set_awaiting_rel(client_id, p.pkid, true);
+                        v4::PubRec::new(p.pkid).write(out).map(drop)?
+                    }
+                }
+            }
+            Packet::PubRel(r) => {
+                sessions.set_awaiting_rel(client_id, r.pkid, false);
+                v4::PubComp::new(r.pkid).write(out).map(drop)?
+            }
+            Packet::Subscribe(s) => {
+                let codes = vec![SubscribeReasonCode::Failure; s.filters.len()];
+                v4::SubAck::new(s.pkid, codes).write(out).map(drop)?
+            }
+            Packet::Unsubscribe(u) => v4::UnsubAck::new(u.pkid).write(out).map(drop)?,
+            Packet::PingReq => v4::PingResp.write(out).map(drop)?,
+            Packet::Disconnect => return Ok(()),
+            other => anyhow::bail!("unexpected packet {other:?}"),
+        }
+        flush(conn.stream, out).await?;
+    }
+}
+
+fn authenticate(cfg: &BrokerCfg, connect: &v4::Connect) -> ConnectReturnCode {
+    if connect.client_id.is_empty() {
+        return ConnectReturnCode::BadClientId;
+    }
+    let Some(client) = cfg
+        .clients
+        .iter()
+        .find(|c| c.client_id == connect.client_id)
+    else {
+        return if cfg.allow_anonymous {
+            ConnectReturnCode::Success
+        } else {
+            ConnectReturnCode::NotAuthorized
+        };
+    };
+    match (&client.username, &connect.login) {
+        (None, _) => ConnectReturnCode::Success,
+        (Some(user), Some(login))
+            if *user == login.username
+                && client.password.as_deref().unwrap_or_default() == login.password =>
+        {
+            ConnectReturnCode::Success
+        }
+        _ => ConnectReturnCode::BadUserNamePassword,
+    }
+}
+
+/// Reads the next packet; `Ok(None)` when the client closed the connection.
+async fn next_packet(
+    stream: &mut TcpStream,
+    buf: &mut BytesMut,
+    max_packet: usize,
+    idle: Duration,
+) -> anyhow::Result<Option<Packet>> {
+    loop {
+        match v4::read(buf, max_packet) {
+            Ok(p) => return Ok(Some(p)),
+            Err(mqttbytes::Error::InsufficientBytes(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let read = tokio::time::timeout(idle, stream.read_buf(buf)).await;
+        match read {
+            Err(_) => anyhow::bail!("keep-alive timeout"),
+            Ok(Ok(0)) => return Ok(None),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Err(e.into()),
+        }
+    }
+}
+
+async fn refuse(stream: &mut TcpStream, code: ConnectReturnCode) -> anyhow::Result<()> {
+    let mut out = BytesMut::new();
+    v4::ConnAck::new(code, false).write(&mut out)?;
+    flush(stream, &mut out).await
+}
+
+async fn flush(stream: &mut TcpStream, out: &mut BytesMut) -> anyhow::Result<()> {
+    if !out.is_empty() {
+        stream.write_all(out).await?;
+        out.clear();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BrokerClient;
+
+    fn cfg() -> BrokerCfg {
+        BrokerCfg {
+            clients: vec![
+                BrokerClient {
+                    client_id: "dev1".into(),
+                    username: None,
+                    password: None,
+                },
+                BrokerClient {
+                    client_id: "dev2".into(),
+                    username: Some("u".into()),
+                    password: Some("p".into()),
+                },
+            ],
+            ..BrokerCfg::default()
+        }
+    }
+
+    #[test]
+    fn connection_slots_never_exceed_the_cap() {
+        let clients = AtomicUsize::new(0);
+        let taken: usize = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(|| {
+                        (0..100)
+                            .filter(|_| take_slot(&clients, 50).is_some())
+                            .count()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+        assert_eq!(taken, 50);
+        assert_eq!(clients.load(Ordering::Relaxed), 50);
+    }
+
+    #[tokio::test]
+    async fn reconnect_takes_over_and_keeps_persistent_qos2_state() {
+        let sessions = Sessions::new(10);
+        let (first, present) = sessions.open("dev1", false);
+        assert!(!present);
+        sessions.set_awaiting_rel("dev1", 7, true);
+
+        let (second, present) = sessions.open("dev1", false);
+        assert!(present);
+        tokio::time::timeout(Duration::from_secs(1), first.notified())
+            .await
+            .expect("old connection not kicked");
+        sessions.close("dev1", &first, false);
+        assert!(sessions.awaiting_rel("dev1", 7));
+
+        sessions.close("dev1", &second, false);
+        assert!(sessions.awaiting_rel("dev1", 7));
+        let (third, present) = sessions.open("dev1", true);
+        assert!(!present);
+        assert!(!sessions.awaiting_rel("dev1", 7));
+        sessions.close("dev1", &third, true);
+        assert!(!sessions.0.lock().unwrap().contains_key("dev1"));
+    }
+
+    #[test]
+    fn authenticates_by_client_id_and_login() {
+        let cfg = cfg();
+        let mut c = v4::Connect::new("dev1");
+        assert_eq!(authenticate(&cfg, &c), ConnectReturnCode::Success);
+
+        c.client_id = "dev2".into();
+        assert_eq!(
+            authenticate(&cfg, &c),
+            ConnectReturnCode::BadUserNamePassword
+        );
+        c.set_login("u", "wrong");
+        assert_eq!(
+            authenticate(&cfg, &c),
+            ConnectReturnCode::BadUserNamePassword
+        );
+        c.set_login("u", "p");
+        assert_eq!(authenticate(&cfg, &c), ConnectReturnCode::Success);
+
+        c.client_id = "unknown".into();
+        assert_eq!(authenticate(&cfg, &c), ConnectReturnCode::NotAuthorized);
+        c.client_id = String::new();
+        assert_eq!(authenticate(&cfg, &c), ConnectReturnCode::BadClientId);
+    }
+}
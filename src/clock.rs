@@ -0,0 +1,356 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use time::{Duration, OffsetDateTime};
+
+use crate::config::{ClockCfg, TsPolicy};
+use crate::device_map::DeviceMap;
+
+/// What happened to a device-supplied `ts` on its way into an `Event`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TsAction {
+    /// No `ts` in the body; `received_at` was used.
+    Defaulted,
+    Kept,
+    /// Shifted by the device's estimated clock offset.
+    Corrected,
+    Clamped,
+    Replaced,
+}
+
+impl TsAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TsAction::Defaulted => "defaulted",
+            TsAction::Kept => "kept",
+            TsAction::Corrected => "corrected",
+            TsAction::Clamped => "clamped",
+            TsAction::Replaced => "replaced",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DeviceClock {
+    offset_ms: f64,
+    samples: u64,
+    last_offset_ms: i64,
+    out_of_window: u64,
+    sync: Option<SyncSample>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SyncSample {
+    /// NTP offset reported by the device: what it must add to its clock.
+    pub offset_ms: i64,
+    pub rtt_ms: u64,
+    pub at_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClockReport {
+    pub device_id: String,
+    /// Smoothed `ts - received_at`, positive when the device clock runs ahead.
+    pub offset_ms: i64,
+    pub last_offset_ms: i64,
+    pub samples: u64,
+    pub out_of_window: u64,
+    /// Last offset measured by the device via `/v1/time`.
+    pub sync: Option<SyncSample>,
+}
+
+/// Sanity-checks device timestamps and keeps a per-device clock offset estimate.
+pub struct ClockTracker {
+    cfg: ClockCfg,
+    devices: Mutex<DeviceMap<DeviceClock>>,
+}
+
+impl ClockTracker {
+    pub fn new(cfg: &ClockCfg, max_devices: usize) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            devices: Mutex::new(DeviceMap::new(max_devices)),
+        }
+    }
+
+    /// Decide the event timestamp for a device-supplied `ts`.
+    /// Errors with a rejection reason when the policy is `reject`.
+    pub fn resolve(
+        &self,
+        device_id: &str,
+        ts: Option<OffsetDateTime>,
+        received_at: OffsetDateTime,
+    ) -> Result<(OffsetDateTime, TsAction), &'static str> {
+        let Some(ts) = ts else {
+            return Ok((received_at, TsAction::Defaulted));
+        };
+
+        let raw_offset_ms = (ts - received_at).whole_milliseconds() as i64;
+        let correction = self.record_offset(device_id, raw_offset_ms, received_at);
+
+        let corrected = correction
+            .filter(|_| self.cfg.correct_drift)
+            .and_then(|offset_ms| ts.checked_sub(Duration::milliseconds(offset_ms)));
+        let (ts, action) = match corrected {
+            Some(ts) => (ts, TsAction::Corrected),
+            None => (ts, TsAction::Kept),
+        };
+
+        let earliest = received_at - Duration::seconds(self.cfg.max_past_s as i64);
+        let latest = received_at + Duration::seconds(self.cfg.max_future_s as i64);
+        if (earliest..=latest).contains(&ts) {
+            return Ok((ts, action));
+        }
+
+        if let Some(d) = self.devices.lock().unwrap().get_mut(device_id) {
+            d.out_of_window += 1;
+        }
+        match self.cfg.out_of_window {
+            TsPolicy::Reject => Err("ts_out_of_window"),
+            TsPolicy::Clamp => Ok((ts.clamp(earliest, latest), TsAction::Clamped)),
+            TsPolicy::Replace => Ok((received_at, TsAction::Replaced)),
+        }
+    }
+
+    /// Fold a raw offset sample into the device estimate. Returns how far the device
+    /// clock runs ahead: its own recent `/v1/time` measurement when it reported one
+    /// (free of transport latency), otherwise the estimate once enough samples back it.
+    fn record_offset(&self, device_id: &str, offset_ms: i64, now: OffsetDateTime) -> Option<i64> {
+        let mut devices = self.devices.lock().unwrap();
+        let d = devices.touch(device_id);
+        let resync_ms = self.cfg.max_future_s as f64 * 1000.0;
+
+        // A jump larger than the future window means the device clock was reset
+        // (reboot, RTC set); start the estimate over rather than smearing it, and
+        // drop the sync result, which measured the old clock.
+        if d.samples == 0 || (offset_ms as f64 - d.offset_ms).abs() > resync_ms {
+            if d.samples > 0 {
+                d.sync = None;
+            }
+            d.offset_ms = offset_ms as f64;
+            d.samples = 1;
+        } else {
+            d.offset_ms += self.cfg.offset_smoothing * (offset_ms as f64 - d.offset_ms);
+            d.samples += 1;
+        }
+        d.last_offset_ms = offset_ms;
+
+        let max_age_ms = self.cfg.sync_max_age_s.saturating_mul(1000) as i64;
+        if let Some(sync) = d.sync {
+            if unix_ms(now) - sync.at_ms <= max_age_ms {
+                return Some(-sync.offset_ms);
+            }
+            d.sync = None;
+        }
+        (d.samples >= self.cfg.min_samples).then(|| d.offset_ms.round() as i64)
+    }
+
+    /// Store the result of a device's `/v1/time` exchange; it replaces the
+    /// estimate for correction until it is `sync_max_age_s` old or the device
+    /// clock is reset. Offsets beyond the accepted `ts` window are refused.
+    pub fn record_sync(&self, device_id: &str, sample: SyncSample) -> Result<(), &'static str> {
+        let max_behind_ms = self.cfg.max_past_s.saturating_mul(1000) as i64;
+        let max_ahead_ms = self.cfg.max_future_s.saturating_mul(1000) as i64;
+        if !(-max_ahead_ms..=max_behind_ms).contains(&sample.offset_ms) {
+            return Err("sync_offset_out_of_window");
+        }
+        let mut devices = self.devices.lock().unwrap();
+        devices.touch(device_id).sync = Some(sample);
+        Ok(())
+    }
+
+    pub fn report(&self, device_id: &str) -> Option<ClockReport> {
+        let devices = self.devices.lock().unwrap();
+        devices.get(device_id).map(|d| to_report(device_id, d))
+    }
+
+    pub fn reports(&self) -> Vec<ClockReport> {
+        let devices = self.devices.lock().unwrap();
+        let mut out: Vec<_> = devices.iter().map(|(id, d)| to_report(id, d)).collect();
+        out.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        out
+    }
+}
+
+pub(crate) fn unix_ms(t: OffsetDateTime) -> i64 {
+    (t.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn to_report(device_id: &str, d: &DeviceClock) -> ClockReport {
+    ClockReport {
+        device_id: device_id.to_string(),
+        offset_ms: d.offset_ms.round() as i64,
+        last_offset_ms: d.last_offset_ms,
+        samples: d.samples,
+        out_of_window: d.out_of_window,
+        sync: d.sync,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noon() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_758_628_800).unwrap()
+    }
+
+    #[test]
+    fn applies_out_of_window_policy() {
+        let now = noon();
+        let epoch = OffsetDateTime::UNIX_EPOCH;
+
+        let replace = ClockTracker::new(&ClockCfg::default(), 100);
+        assert_eq!(
+            replace.resolve("d1", Some(epoch), now),
+            Ok((now, TsAction::Replaced))
+        );
+
+        let clamp = ClockTracker::new(
+            &ClockCfg {
+                out_of_window: TsPolicy::Clamp,
+                ..ClockCfg::default()
+            },
+            100,
+        );
+        let future = now + Duration::days(1);
+        assert_eq!(
+            clamp.resolve("d1", Some(future), now),
+            Ok((now + Duration::seconds(300), TsAction::Clamped))
+        );
+
+        let reject = ClockTracker::new(
+            &ClockCfg {
+                out_of_window: TsPolicy::Reject,
+                ..ClockCfg::default()
+            },
+            100,
+        );
+        assert_eq!(
+            reject.resolve("d1", Some(epoch), now),
+            Err("ts_out_of_window")
+        );
+    }
+
+    #[test]
+    fn corrects_consistent_drift_once_estimate_is_trusted() {
+        let cfg = ClockCfg {
+            correct_drift: true,
+            min_samples: 3,
+            ..ClockCfg::default()
+        };
+        let t = ClockTracker::new(&cfg, 100);
+        let now = noon();
+        let skew = Duration::seconds(90);
+
+        for i in 0..2 {
+            let at = now + Duration::seconds(i);
+            assert_eq!(
+                t.resolve("d1", Some(at + skew), at).unwrap().1,
+                TsAction::Kept
+            );
+        }
+        let at = now + Duration::seconds(2);
+        assert_eq!(
+            t.resolve("d1", Some(at + skew), at),
+            Ok((at, TsAction::Corrected))
+        );
+        assert_eq!(t.report("d1").unwrap().offset_ms, 90_000);
+    }
+
+    #[test]
+    fn keeps_last_sync_sample_in_report() {
+        let t = ClockTracker::new(&ClockCfg::default(), 100);
+        let sample = SyncSample {
+            offset_ms: -1500,
+            rtt_ms: 40,
+            at_ms: 1_758_628_800_000,
+        };
+        t.record_sync("d1", sample).unwrap();
+
+        let sync = t.report("d1").unwrap().sync.unwrap();
+        assert_eq!((sync.offset_ms, sync.rtt_ms), (-1500, 40));
+    }
+
+    #[test]
+    fn corrects_with_reported_sync_offset() {
+        let t = ClockTracker::new(
+            &ClockCfg {
+                correct_drift: true,
+                ..ClockCfg::default()
+            },
+            100,
+        );
+        // The device clock runs 1.5 s ahead, so it must add -1500 ms.
+        t.record_sync(
+            "d1",
+            SyncSample {
+                offset_ms: -1500,
+                rtt_ms: 40,
+                at_ms: 1_758_628_800_000,
+            },
+        )
+        .unwrap();
+        let now = noon();
+        let ts = now - Duration::seconds(60);
+        assert_eq!(
+            t.resolve("d1", Some(ts + Duration::milliseconds(1500)), now),
+            Ok((ts, TsAction::Corrected))
+        );
+    }
+
+    #[test]
+    fn refuses_sync_offsets_outside_the_window() {
+        let t = ClockTracker::new(&ClockCfg::default(), 100);
+        for offset_ms in [i64::MIN, -300_001, 7 * 24 * 3600 * 1000 + 1, i64::MAX] {
+            let sample = SyncSample {
+                offset_ms,
+                rtt_ms: 0,
+                at_ms: 1_758_628_800_000,
+            };
+            assert_eq!(
+                t.record_sync("d1", sample),
+                Err("sync_offset_out_of_window")
+            );
+        }
+        assert!(t.report("d1").is_none());
+    }
+
+    #[test]
+    fn sync_offset_expires_and_is_dropped_on_clock_reset() {
+        let t = ClockTracker::new(
+            &ClockCfg {
+                correct_drift: true,
+                ..ClockCfg::default()
+            },
+            100,
+        );
+        let now = noon();
+        let sync = SyncSample {
+            offset_ms: -1500,
+            rtt_ms: 40,
+            at_ms: unix_ms(now),
+        };
+        let ahead = Duration::milliseconds(1500);
+
+        t.record_sync("d1", sync).unwrap();
+        let later = now + Duration::hours(2);
+        assert_eq!(
+            t.resolve("d1", Some(later + ahead), later).unwrap().1,
+            TsAction::Kept
+        );
+        assert!(t.report("d1").unwrap().sync.is_none());
+
+        t.record_sync("d2", sync).unwrap();
+        assert_eq!(
+            t.resolve("d2", Some(now + ahead), now).unwrap().1,
+            TsAction::Corrected
+        );
+        // The RTC was set back an hour: the reported offset no longer applies.
+        let reset = now - Duration::hours(1);
+        assert_eq!(
+            t.resolve("d2", Some(reset), now),
+            Ok((reset, TsAction::Kept))
+        );
+        assert!(t.report("d2").unwrap().sync.is_none());
+    }
+}
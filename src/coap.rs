@@ -0,0 +1,214 @@
+//! Minimal CoAP (RFC 7252) ingest listener over UDP. `POST /ingest/{device_id}`
+//! goes through the same validation and pipeline as the HTTP handler.
+
+use coap_lite::{
+    CoapOption, ContentFormat, MessageClass, MessageType, Packet, RequestType, ResponseType,
+};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+use crate::app::AppState;
+use crate::config::CoapCfg;
+use crate::ingest::format::BodyFormat;
+use crate::ingest::pipeline::{self, IngestError};
+
+/// RFC 7252 §4.8.2 EXCHANGE_LIFETIME with default transmission parameters.
+const EXCHANGE_LIFETIME: Duration = Duration::from_secs(247);
+const MAX_CACHED_EXCHANGES: usize = 4096;
+
+/// Remembers responses per (peer, message id) so retransmitted CON/NON
+/// messages get the same answer instead of being ingested twice.
+struct ExchangeCache {
+    responses: HashMap<(SocketAddr, u16), Vec<u8>>,
+    order: VecDeque<(Instant, (SocketAddr, u16))>,
+}
+
+impl ExchangeCache {
+    fn new() -> Self {
+        Self {
+            responses: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, key: &(SocketAddr, u16)) -> Option<&Vec<u8>> {
+        self.responses.get(key)
+    }
+
+    fn insert(&mut self, key: (SocketAddr, u16), response: Vec<u8>, now: Instant) {
+        while let Some((at, old)) = self.order.front() {
+            if now.duration_since(*at) < EXCHANGE_LIFETIME
+                && self.order.len() < MAX_CACHED_EXCHANGES
+            {
+                break;
+            }
+            self.responses.remove(old);
+            self.order.pop_front();
+        }
+        if self.responses.insert(key, response).is_none() {
+            self.order.push_back((now, key));
+        }
+    }
+}
+
+pub async fn serve(cfg: CoapCfg, st: AppState) -> anyhow::Result<()> {
+    let sock = UdpSocket::bind(cfg.bind).await?;
+    tracing::info!(addr = %sock.local_addr()?, "coap listening");
+
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut cache = ExchangeCache::new();
+    let mut next_mid: u16 = rand_mid();
+    let stopped = st.ready.stopped();
+    tokio::pin!(stopped);
+    loop {
+        let received = tokio::select! {
+            r = sock.recv_from(&mut buf) => r,
+            _ = &mut stopped => break,
+        };
+        let (n, peer) = match received {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!(error = %e, "coap receive failed");
+                st.metrics.coap_request("io_error");
+                continue;
+            }
+        };
+        let Ok(req) = Packet::from_bytes(&buf[..n]) else {
+            st.metrics.coap_request("malformed");
+            continue;
+        };
+        let mid = req.header.message_id;
+        let key = (peer, mid);
+
+        let reply = match req.header.get_type() {
+            MessageType::Acknowledgement | MessageType::Reset => continue,
+            _ if cache.get(&key).is_some() => cache.get(&key).cloned(),
+            MessageType::Confirmable if req.header.code == MessageClass::Empty => {
+                // CoAP ping.
+                Some(empty(MessageType::Reset, mid))
+            }
+            MessageType::NonConfirmable if req.header.code == MessageClass::Empty => None,
+            kind => {
+                let (code, diagnostic) = handle(&st, &req, n);
+                st.metrics.coap_request(code_label(code));
+                let mut resp = Packet::new();
+                if kind == MessageType::Confirmable {
+                    resp.header.set_type(MessageType::Acknowledgement);
+                    resp.header.message_id = mid;
+                } else {
+                    resp.header.set_type(MessageType::NonConfirmable);
+                    next_mid = next_mid.wrapping_add(1);
+                    resp.header.message_id = next_mid;
+                }
+                resp.header.code = MessageClass::Response(code);
+                resp.set_token(req.get_token().to_vec());
+                if let Some(diagnostic) = diagnostic {
+                    resp.payload = diagnostic.as_bytes().to_vec();
+                }
+                let bytes = match resp.to_bytes() {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        tracing::warn!(%peer, error = ?e, "coap response not encodable");
+                        st.metrics.coap_request("encode_error");
+                        continue;
+                    }
+                };
+                cache.insert(key, bytes.clone(), Instant::now());
+                Some(bytes)
+            }
+        };
+        if let Some(reply) = reply
+            && let Err(e) = sock.send_to(&reply, peer).await
+        {
+            tracing::warn!(%peer, error = %e, "coap send failed");
+            st.metrics.coap_request("io_error");
+        }
+    }
+    tracing::info!("coap listener stopped");
+    Ok(())
+}
+
+fn handle(
+    st: &AppState,
+    req: &Packet,
+    datagram_len: usize,
+) -> (ResponseType, Option<&'static str>) {
+    let path: Vec<&[u8]> = req
+        .get_option(CoapOption::UriPath)
+        .map(|segments| segments.iter().map(Vec::as_slice).collect())
+        .unwrap_or_default();
+    let device_id = match path.as_slice() {
+        [b"ingest", device_id] => match std::str::from_utf8(device_id) {
+            Ok(id) if !id.is_empty() => id,
+            _ => return (ResponseType::NotFound, None),
+        },
+        _ => return (ResponseType::NotFound, None),
+    };
+    if req.header.code != MessageClass::Request(RequestType::Post) {
+        return (ResponseType::MethodNotAllowed, None);
+    }
+    if req.payload.len() > st.cfg.ingest.max_payload_bytes {
+        return (ResponseType::RequestEntityTooLarge, None);
+    }
+    let format = match req.get_content_format() {
+        None | Some(ContentFormat::ApplicationJSON) => BodyFormat::Json,
+        Some(ContentFormat::ApplicationCBOR) => BodyFormat::Cbor,
+        Some(_) => return (ResponseType::UnsupportedContentFormat, None),
+    };
+    let Ok(body) = format.decode_ingest(&req.payload) else {
+        return (ResponseType::BadRequest, Some("invalid_body"));
+    };
+
+    match pipeline::submit(st, device_id, body, datagram_len) {
+        Ok(_) => (ResponseType::Changed, None),
+        Err(IngestError::Invalid(reason)) => (ResponseType::BadRequest, Some(reason)),
+        Err(e) => (ResponseType::ServiceUnavailable, Some(e.code())),
+    }
+}
+
+fn empty(kind: MessageType, mid: u16) -> Vec<u8> {
+    let mut p = Packet::new();
+    p.header.set_type(kind);
+    p.header.message_id = mid;
+    p.to_bytes().unwrap_or_default()
+}
+
+fn code_label(code: ResponseType) -> &'static str {
+    match code {
+        ResponseType::Changed => "2.04",
+        ResponseType::BadRequest => "4.00",
+        ResponseType::NotFound => "4.04",
+        ResponseType::MethodNotAllowed => "4.05",
+        ResponseType::RequestEntityTooLarge => "4.13",
+        ResponseType::UnsupportedContentFormat => "4.15",
+        ResponseType::ServiceUnavailable => "5.03",
+        _ => "other",
+    }
+}
+
+/// Start NON message ids somewhere unpredictable, as RFC 7252 §4.4 recommends.
+fn rand_mid() -> u16 {
+    use std::hash::{BuildHasher, RandomState};
+    RandomState::new().hash_one(Instant::now()) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exchange_cache_expires_old_entries() {
+        let mut cache = ExchangeCache::new();
+        let peer: SocketAddr = "127.0.0.1:5683".parse().unwrap();
+        let t0 = Instant::now();
+
+        cache.insert((peer, 1), vec![1], t0);
+        assert_eq!(cache.get(&(peer, 1)), Some(&vec![1]));
+
+        cache.insert((peer, 2), vec![2], t0 + EXCHANGE_LIFETIME);
+        assert_eq!(cache.get(&(peer, 1)), None);
+        assert_eq!(cache.get(&(peer, 2)), Some(&vec![2]));
+    }
+}
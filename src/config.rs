@@ -1,8 +1,10 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
 };
+use utoipa::ToSchema;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct GatewayGfg {
@@ -16,6 +18,28 @@ pub struct GatewayGfg {
     pub health: HealthCfg,
     #[serde(default)]
     pub ingest: IngestCfg,
+    #[serde(default)]
+    pub sequence: SequenceCfg,
+    #[serde(default)]
+    pub clock: ClockCfg,
+    #[serde(default)]
+    pub coap: CoapCfg,
+    #[serde(default)]
+    pub mqtt_ingress: MqttIngressCfg,
+    #[serde(default)]
+    pub broker: BrokerCfg,
+    #[serde(default)]
+    pub lines: LinesCfg,
+    #[serde(default)]
+    pub grpc: GrpcCfg,
+    #[serde(default)]
+    pub decoders: Vec<DecoderCfg>,
+    #[serde(default)]
+    pub modbus: Vec<ModbusCfg>,
+    #[serde(default)]
+    pub serial: Vec<SerialCfg>,
+    #[serde(default)]
+    pub metric_descriptors: Vec<MetricDescriptorCfg>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -49,6 +73,140 @@ impl Default for MqttCfg {
     }
 }
 
+/// Subscriber pulling device telemetry from a local broker (e.g. Mosquitto).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct MqttIngressCfg {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic filters to subscribe to.
+    pub topics: Vec<String>,
+    /// Where the device id sits in the topic, e.g. `site/{device_id}/telemetry`.
+    pub device_id_template: String,
+    pub qos: u8,
+    pub keep_alive_s: u64,
+    pub reconnect_delay_ms: u64,
+    /// 100 ms retries while the pipeline is backed up before a message is dropped.
+    pub backpressure_retries: u32,
+}
+impl Default for MqttIngressCfg {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".into(),
+            port: 1883,
+            client_id: "gw-ingress-1".into(),
+            username: None,
+            password: None,
+            topics: vec!["site/+/telemetry".into()],
+            device_id_template: "site/{device_id}/telemetry".into(),
+            qos: 1,
+            keep_alive_s: 30,
+            reconnect_delay_ms: 2000,
+            backpressure_retries: 20,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct GrpcCfg {
+    pub enabled: bool,
+    pub bind: SocketAddr,
+}
+impl Default for GrpcCfg {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "0.0.0.0:50051".parse().unwrap(),
+        }
+    }
+}
+
+/// Plain TCP/UDP line listeners; each is off unless its bind address is set.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct LinesCfg {
+    pub tcp_bind: Option<SocketAddr>,
+    pub udp_bind: Option<SocketAddr>,
+    /// TCP connections idle this long are closed.
+    pub idle_timeout_s: u64,
+    /// Concurrent TCP connections; further ones are closed right away.
+    pub max_connections: usize,
+    /// Device id for senders whose lines carry none, keyed by source IP.
+    pub devices: BTreeMap<IpAddr, String>,
+}
+impl Default for LinesCfg {
+    fn default() -> Self {
+        Self {
+            tcp_bind: None,
+            udp_bind: None,
+            idle_timeout_s: 300,
+            max_connections: 256,
+            devices: BTreeMap::new(),
+        }
+    }
+}
+
+/// Embedded MQTT 3.1.1 broker devices can publish to directly.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct BrokerCfg {
+    pub enabled: bool,
+    pub bind: SocketAddr,
+    pub max_connections: usize,
+    /// Publishes on this topic are ingested; `{device_id}` marks the device segment.
+    pub telemetry_topic: String,
+    /// Accept client ids that are not listed in `clients`.
+    pub allow_anonymous: bool,
+    /// Only let a client publish telemetry for the device matching its client id.
+    pub bind_device_to_client_id: bool,
+    pub backpressure_retries: u32,
+    pub clients: Vec<BrokerClient>,
+}
+impl Default for BrokerCfg {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "0.0.0.0:1883".parse().unwrap(),
+            max_connections: 1000,
+            telemetry_topic: "devices/{device_id}/telemetry".into(),
+            allow_anonymous: false,
+            bind_device_to_client_id: true,
+            backpressure_retries: 20,
+            clients: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BrokerClient {
+    pub client_id: String,
+    /// When set, CONNECT must carry this username and `password`.
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct CoapCfg {
+    pub enabled: bool,
+    pub bind: SocketAddr,
+}
+impl Default for CoapCfg {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 5683),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 #[serde(deny_unknown_fields, default)]
 pub struct StorageCfg {
@@ -68,6 +226,8 @@ impl Default for StorageCfg {
 pub struct HealthCfg {
     pub require_mqtt: bool,
     pub require_disk: bool,
+    /// Only ready once the embedded MQTT broker is listening.
+    pub require_broker: bool,
     pub probe_interval_ms: Option<u64>,
 }
 impl Default for HealthCfg {
@@ -75,41 +235,348 @@ impl Default for HealthCfg {
         Self {
             require_mqtt: (false),
             require_disk: (false),
+            require_broker: (false),
             probe_interval_ms: Some(1000),
         }
     }
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct IngestCfg {
     pub max_payload_bytes: usize,
     pub queue_capacity: usize,
+    /// Upper bound on the summed (decompressed) body size of events queued in the pipeline.
+    pub max_inflight_bytes: usize,
+    pub max_batch_items: usize,
+    pub max_batch_bytes: usize,
     pub ack_mode: AckMode,
     pub require_auth: bool,
+    /// Line-protocol tag holding the device id on `/api/v2/write` (Telegraf sets `host`).
+    pub influx_device_tag: String,
+    /// OTLP attributes tried in order for the device id on `/v1/metrics`.
+    pub otlp_device_attributes: Vec<String>,
+    /// Distinct label sets accepted per device on Prometheus pushes.
+    pub prometheus_max_series: usize,
+    /// Devices whose seq, clock and series state (and broker clients whose
+    /// session) is kept; the least recently seen one is forgotten beyond this.
+    pub max_tracked_devices: usize,
 }
 impl Default for IngestCfg {
     fn default() -> Self {
         Self {
             max_payload_bytes: 65536,
             queue_capacity: 10000,
+            max_inflight_bytes: 16 * 1024 * 1024,
+            max_batch_items: 500,
+            max_batch_bytes: 1024 * 1024,
             ack_mode: AckMode::Enqueue,
             require_auth: false,
+            influx_device_tag: "host".into(),
+            otlp_device_attributes: vec![
+                "device.id".into(),
+                "host.name".into(),
+                "service.instance.id".into(),
+            ],
+            prometheus_max_series: 1000,
+            max_tracked_devices: 10_000,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum AckMode {
+    #[default]
     Enqueue,
     Sink,
 }
-impl Default for AckMode {
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct SequenceCfg {
+    /// Hold out-of-order events and emit them to sinks in `seq` order.
+    pub reorder: bool,
+    pub reorder_hold_ms: u64,
+    pub reorder_max_pending: usize,
+    /// How far behind the stream a `seq` counts as a duplicate rather than a counter reset.
+    pub dedupe_window: u64,
+    /// Acknowledge duplicates without enqueuing them again. Off by default: a device
+    /// that restarts its counter inside the window would otherwise lose readings.
+    pub drop_duplicates: bool,
+    pub max_missing_tracked: usize,
+}
+impl Default for SequenceCfg {
     fn default() -> Self {
-        AckMode::Enqueue
+        Self {
+            reorder: false,
+            reorder_hold_ms: 2000,
+            reorder_max_pending: 256,
+            dedupe_window: 1024,
+            drop_duplicates: false,
+            max_missing_tracked: 1024,
+        }
     }
 }
 
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct ClockCfg {
+    /// Oldest accepted `ts`, in seconds before `received_at`.
+    pub max_past_s: u64,
+    /// Newest accepted `ts`, in seconds after `received_at`.
+    pub max_future_s: u64,
+    pub out_of_window: TsPolicy,
+    /// Shift device timestamps by the estimated per-device clock offset.
+    pub correct_drift: bool,
+    /// EWMA weight of a new offset sample, in (0, 1].
+    pub offset_smoothing: f64,
+    /// Samples needed before the offset estimate is used for correction.
+    pub min_samples: u64,
+    /// How long an offset reported to `POST /v1/time/{device_id}` is used.
+    pub sync_max_age_s: u64,
+}
+impl Default for ClockCfg {
+    fn default() -> Self {
+        Self {
+            max_past_s: 7 * 24 * 3600,
+            max_future_s: 300,
+            out_of_window: TsPolicy::Replace,
+            correct_drift: false,
+            offset_smoothing: 0.1,
+            min_samples: 5,
+            sync_max_age_s: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TsPolicy {
+    Reject,
+    Clamp,
+    /// Use `received_at` instead.
+    #[default]
+    Replace,
+}
+
+/// What a metric name means: unit, kind and the range of plausible values.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MetricDescriptorCfg {
+    pub name: String,
+    /// E.g. `Cel`, `%RH`, `Wh` (UCUM/SenML style recommended).
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub kind: MetricKind,
+    pub description: Option<String>,
+    /// Values outside `[min, max]` are rejected; either bound may be omitted.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    #[default]
+    Gauge,
+    /// Monotonic count; must be a non-negative number.
+    Counter,
+}
+
+/// Layout of a packed binary payload (e.g. a C struct sent by firmware), expanded
+/// into metrics at ingest time.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DecoderCfg {
+    pub name: String,
+    /// Devices this decoder applies to.
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Or: events carrying all of these tags.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Where the base64 blob sits in a JSON `payload`: the payload itself when unset,
+    /// else this member (e.g. `frm_payload` for LoRaWAN uplinks).
+    pub payload_field: Option<String>,
+    pub fields: Vec<DecoderFieldCfg>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DecoderFieldCfg {
+    /// Metric name.
+    pub name: String,
+    /// Byte offset in the blob.
+    pub offset: usize,
+    #[serde(rename = "type")]
+    pub kind: FieldType,
+    #[serde(default)]
+    pub endian: Endian,
+    /// The metric is `raw * scale + value_offset`.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub value_offset: f64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    F64,
+}
+
+impl FieldType {
+    pub fn size(self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 4,
+            FieldType::F64 => 8,
+        }
+    }
+}
+
+/// Byte order of multi-byte fields; STM32 (Cortex-M) is little-endian.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+/// A Modbus TCP slave polled on an interval; each poll becomes one event.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ModbusCfg {
+    pub device_id: String,
+    /// `host:port` of the slave (or a Modbus TCP gateway).
+    pub address: String,
+    #[serde(default = "default_unit_id")]
+    pub unit_id: u8,
+    #[serde(default = "default_modbus_interval_ms")]
+    pub interval_ms: u64,
+    /// Applies to connecting and to each request.
+    #[serde(default = "default_modbus_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    pub registers: Vec<ModbusRegisterCfg>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ModbusRegisterCfg {
+    /// Metric name.
+    pub name: String,
+    #[serde(default)]
+    pub table: RegisterTable,
+    /// Zero-based register address (protocol address, not the 4xxxx number).
+    pub address: u16,
+    /// 16-bit types use one register, 32-bit two, `f64` four.
+    #[serde(rename = "type")]
+    pub kind: FieldType,
+    /// Order of the registers making up a multi-register value; bytes within a
+    /// register are always big-endian.
+    #[serde(default = "default_word_order")]
+    pub word_order: Endian,
+    /// The metric is `raw * scale + value_offset`.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub value_offset: f64,
+}
+
+impl ModbusRegisterCfg {
+    pub fn count(&self) -> u16 {
+        self.kind.size().div_ceil(2) as u16
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterTable {
+    #[default]
+    Holding,
+    Input,
+}
+
+/// A tty (e.g. a USB-serial dev board) read line by line.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SerialCfg {
+    pub path: PathBuf,
+    #[serde(default = "default_baud")]
+    pub baud: u32,
+    #[serde(default = "default_data_bits")]
+    pub data_bits: u8,
+    #[serde(default)]
+    pub parity: Parity,
+    #[serde(default = "default_stop_bits")]
+    pub stop_bits: u8,
+    /// Device id for lines that carry none.
+    pub device_id: Option<String>,
+    /// Lines are hex-encoded frames for this `[[decoders]]` entry instead of
+    /// JSON / `key=value`; requires `device_id`.
+    pub decoder: Option<String>,
+    /// Wait before reopening the port after an error or unplug.
+    #[serde(default = "default_serial_reconnect_ms")]
+    pub reconnect_delay_ms: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+fn default_baud() -> u32 {
+    115_200
+}
+
+fn default_data_bits() -> u8 {
+    8
+}
+
+fn default_stop_bits() -> u8 {
+    1
+}
+
+fn default_serial_reconnect_ms() -> u64 {
+    2000
+}
+
+fn default_unit_id() -> u8 {
+    1
+}
+
+fn default_modbus_interval_ms() -> u64 {
+    5000
+}
+
+fn default_modbus_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_word_order() -> Endian {
+    Endian::Big
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
 fn default_bind() -> SocketAddr {
     SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8080)
 }
@@ -121,6 +588,141 @@ impl GatewayGfg {
 
     pub fn validate(&self) -> anyhow::Result<()> {
         anyhow::ensure!(!self.mqtt.host.is_empty(), "mqtt.host cannot be empty");
+        anyhow::ensure!(
+            self.ingest.max_inflight_bytes >= self.ingest.max_payload_bytes,
+            "ingest.max_inflight_bytes must be >= ingest.max_payload_bytes"
+        );
+        anyhow::ensure!(
+            self.ingest.max_batch_items > 0,
+            "ingest.max_batch_items must be > 0"
+        );
+        anyhow::ensure!(
+            self.clock.offset_smoothing > 0.0 && self.clock.offset_smoothing <= 1.0,
+            "clock.offset_smoothing must be in (0, 1]"
+        );
+        let mut decoder_names = std::collections::HashSet::new();
+        for d in &self.decoders {
+            anyhow::ensure!(
+                decoder_names.insert(d.name.as_str()),
+                "decoder `{}` is defined twice",
+                d.name
+            );
+            anyhow::ensure!(
+                !d.devices.is_empty() || !d.tags.is_empty(),
+                "decoder `{}` needs `devices` or `tags` to select it",
+                d.name
+            );
+            anyhow::ensure!(!d.fields.is_empty(), "decoder `{}` has no fields", d.name);
+            for f in &d.fields {
+                anyhow::ensure!(
+                    f.scale.is_finite() && f.value_offset.is_finite(),
+                    "decoder `{}` field `{}`: scale and value_offset must be finite",
+                    d.name,
+                    f.name
+                );
+            }
+        }
+        for m in &self.modbus {
+            anyhow::ensure!(
+                !m.device_id.is_empty(),
+                "modbus.device_id must not be empty"
+            );
+            anyhow::ensure!(
+                m.interval_ms > 0 && m.timeout_ms > 0,
+                "modbus `{}`: interval_ms and timeout_ms must be > 0",
+                m.device_id
+            );
+            anyhow::ensure!(
+                !m.registers.is_empty(),
+                "modbus `{}` has no registers",
+                m.device_id
+            );
+            for r in &m.registers {
+                anyhow::ensure!(
+                    r.kind.size() >= 2,
+                    "modbus `{}` register `{}`: 8-bit types are not addressable",
+                    m.device_id,
+                    r.name
+                );
+                anyhow::ensure!(
+                    r.scale.is_finite() && r.value_offset.is_finite(),
+                    "modbus `{}` register `{}`: scale and value_offset must be finite",
+                    m.device_id,
+                    r.name
+                );
+            }
+        }
+        let mut described = std::collections::HashSet::new();
+        for d in &self.metric_descriptors {
+            anyhow::ensure!(
+                described.insert(d.name.as_str()),
+                "metric descriptor `{}` is defined twice",
+                d.name
+            );
+            if let (Some(min), Some(max)) = (d.min, d.max) {
+                anyhow::ensure!(min <= max, "metric descriptor `{}`: min > max", d.name);
+            }
+        }
+        for s in &self.serial {
+            let path = s.path.display();
+            anyhow::ensure!(
+                crate::serial::baud_rate(s.baud).is_some(),
+                "serial `{path}`: unsupported baud rate {}",
+                s.baud
+            );
+            anyhow::ensure!(
+                (5..=8).contains(&s.data_bits),
+                "serial `{path}`: data_bits must be 5..=8"
+            );
+            anyhow::ensure!(
+                matches!(s.stop_bits, 1 | 2),
+                "serial `{path}`: stop_bits must be 1 or 2"
+            );
+            if let Some(decoder) = &s.decoder {
+                anyhow::ensure!(
+                    self.decoders.iter().any(|d| &d.name == decoder),
+                    "serial `{path}`: unknown decoder `{decoder}`"
+                );
+                anyhow::ensure!(
+                    s.device_id.is_some(),
+                    "serial `{path}`: a decoder needs device_id"
+                );
+            }
+        }
+        if self.lines.tcp_bind.is_some() {
+            anyhow::ensure!(
+                self.lines.max_connections > 0,
+                "lines.max_connections must be > 0"
+            );
+        }
+        if self.broker.enabled {
+            anyhow::ensure!(
+                self.broker.max_connections > 0,
+                "broker.max_connections must be > 0"
+            );
+            crate::mqtt_ingress::TopicTemplate::parse(&self.broker.telemetry_topic)?;
+        }
+        if self.mqtt_ingress.enabled {
+            anyhow::ensure!(
+                !self.mqtt_ingress.topics.is_empty(),
+                "mqtt_ingress.topics cannot be empty"
+            );
+            anyhow::ensure!(
+                self.mqtt_ingress.qos <= 2,
+                "mqtt_ingress.qos must be 0, 1 or 2"
+            );
+            crate::mqtt_ingress::TopicTemplate::parse(&self.mqtt_ingress.device_id_template)?;
+        }
+        if self.sequence.reorder {
+            anyhow::ensure!(
+                self.sequence.reorder_hold_ms > 0,
+                "sequence.reorder_hold_ms must be > 0 when reorder is enabled"
+            );
+            anyhow::ensure!(
+                self.sequence.reorder_max_pending > 0,
+                "sequence.reorder_max_pending must be > 0 when reorder is enabled"
+            );
+        }
         Ok(())
     }
 
@@ -0,0 +1,124 @@
+//! Per-device state keyed by a client-supplied device id. The number of devices
+//! is capped so arbitrary ids can't grow memory without bound; making room
+//! forgets the device that was touched least recently.
+
+use std::collections::{BTreeMap, HashMap};
+
+pub struct DeviceMap<V> {
+    max_devices: usize,
+    tick: u64,
+    entries: HashMap<String, (V, u64)>,
+    /// Last-touched tick → device id, oldest first.
+    by_use: BTreeMap<u64, String>,
+}
+
+impl<V: Default> DeviceMap<V> {
+    pub fn new(max_devices: usize) -> Self {
+        Self {
+            max_devices: max_devices.max(1),
+            tick: 0,
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+        }
+    }
+
+    pub fn contains_key(&self, device_id: &str) -> bool {
+        self.entries.contains_key(device_id)
+    }
+
+    pub fn get(&self, device_id: &str) -> Option<&V> {
+        self.entries.get(device_id).map(|(v, _)| v)
+    }
+
+    /// Mutable access without counting as a use.
+    pub fn get_mut(&mut self, device_id: &str) -> Option<&mut V> {
+        self.entries.get_mut(device_id).map(|(v, _)| v)
+    }
+
+    /// The device's state, created if needed, marked as most recently used.
+    pub fn touch(&mut self, device_id: &str) -> &mut V {
+        self.touch_evicting(device_id).0
+    }
+
+    /// Like [`touch`](Self::touch), also handing back the state of the device
+    /// forgotten to make room, if any.
+    pub fn touch_evicting(&mut self, device_id: &str) -> (&mut V, Option<V>) {
+        self.tick += 1;
+        let tick = self.tick;
+        let mut evicted = None;
+        if let Some((_, used)) = self.entries.get_mut(device_id) {
+            self.by_use.remove(used);
+            *used = tick;
+        } else {
+            if self.entries.len() >= self.max_devices
+                && let Some((_, oldest)) = self.by_use.pop_first()
+            {
+                evicted = self.entries.remove(&oldest).map(|(v, _)| v);
+            }
+            self.entries
+                .insert(device_id.to_string(), (V::default(), tick));
+        }
+        self.by_use.insert(tick, device_id.to_string());
+        let v = &mut self.entries.get_mut(device_id).expect("just inserted").0;
+        (v, evicted)
+    }
+
+    pub fn remove(&mut self, device_id: &str) -> Option<V> {
+        let (v, used) = self.entries.remove(device_id)?;
+        self.by_use.remove(&used);
+        Some(v)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        self.entries.iter().map(|(id, (v, _))| (id, v))
+    }
+
+    /// Mutable iteration without counting as a use.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.entries.values_mut().map(|(v, _)| v)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&V) -> bool) {
+        let by_use = &mut self.by_use;
+        self.entries.retain(|_, (v, used)| {
+            let kept = keep(v);
+            if !kept {
+                by_use.remove(used);
+            }
+            kept
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_least_recently_used_device_at_cap() {
+        let mut m: DeviceMap<u32> = DeviceMap::new(2);
+        *m.touch("a") += 1;
+        *m.touch("b") += 1;
+        *m.touch("a") += 1;
+        *m.touch("c") += 1;
+
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.get("a"), Some(&2));
+        assert!(!m.contains_key("b"));
+        assert_eq!(m.get("c"), Some(&1));
+
+        for i in 0..1000 {
+            m.touch(&format!("spoofed-{i}"));
+        }
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.by_use.len(), 2);
+    }
+}
@@ -1,21 +1,68 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use crate::domain::Event;
 use crate::fanout::FanoutSink;
+use crate::ingest::budget::ByteBudget;
+use crate::metrics::AppMetrics;
+use crate::sequence::ReorderBuffer;
 
 pub struct Dispatcher {
     rx: tokio::sync::mpsc::Receiver<Event>,
     fanout: Arc<FanoutSink>,
+    inflight: Arc<ByteBudget>,
+    metrics: Arc<AppMetrics>,
+    reorder: Option<ReorderBuffer>,
 }
 
 impl Dispatcher {
-    pub fn new(rx: tokio::sync::mpsc::Receiver<Event>, fanout: Arc<FanoutSink>) -> Self {
-        Self { rx, fanout }
+    pub fn new(
+        rx: tokio::sync::mpsc::Receiver<Event>,
+        fanout: Arc<FanoutSink>,
+        inflight: Arc<ByteBudget>,
+        metrics: Arc<AppMetrics>,
+    ) -> Self {
+        Self {
+            rx,
+            fanout,
+            inflight,
+            metrics,
+            reorder: None,
+        }
+    }
+
+    pub fn with_reorder(mut self, reorder: ReorderBuffer) -> Self {
+        self.reorder = Some(reorder);
+        self
     }
 
     pub async fn run(mut self) {
-        while let Some(ev) = self.rx.recv().await {
-            let accepted = self.fanout.try_enqueue(ev);
+        let Some(mut reorder) = self.reorder.take() else {
+            while let Some(ev) = self.rx.recv().await {
+                self.dispatch(ev);
+            }
+            return;
+        };
+
+        let mut tick = tokio::time::interval(reorder.tick_interval());
+        loop {
+            let ready = tokio::select! {
+                ev = self.rx.recv() => match ev {
+                    Some(ev) => reorder.push(ev, Instant::now()),
+                    None => break,
+                },
+                _ = tick.tick() => reorder.flush_expired(Instant::now()),
+            };
+            ready.into_iter().for_each(|ev| self.dispatch(ev));
         }
+        reorder.drain().into_iter().for_each(|ev| self.dispatch(ev));
+    }
+
+    /// Hand an event to the sinks; from here on it no longer counts against the in-flight budget.
+    fn dispatch(&self, ev: Event) {
+        self.inflight.release(ev.bytes);
+        self.metrics.inflight_bytes(self.inflight.used());
+        tracing::debug!(event_id = %ev.id, device_id = %ev.device_id, "event dispatched");
+        let _accepted = self.fanout.try_enqueue(ev);
     }
 }
@@ -1,13 +1,21 @@
 use serde::Serialize;
 use std::collections::BTreeMap;
 use time::OffsetDateTime;
+use ulid::Ulid;
+
+use crate::domain::MetricValue;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Event {
+    /// Gateway-assigned at ingest and returned to the client, for end-to-end tracing.
+    pub id: Ulid,
     pub device_id: String,
     pub ts: OffsetDateTime,
     pub seq: Option<u64>,
-    pub metrics: BTreeMap<String, f64>,
+    pub metrics: BTreeMap<String, MetricValue>,
+    /// Units by metric name, from the source or a descriptor (which wins).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub units: BTreeMap<String, String>,
     pub tags: BTreeMap<String, String>,
     pub payload: serde_json::Value,
     pub received_at: OffsetDateTime,
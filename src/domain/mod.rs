@@ -1,2 +1,4 @@
 pub mod event;
+pub mod value;
 pub use event::Event;
+pub use value::MetricValue;
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// One metric value. Plain numbers keep working: integers stay exact as
+/// `UInt`/`Int` (no rounding above 2^53), everything else with a fraction or
+/// exponent is a `Float`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum MetricValue {
+    Bool(bool),
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+    /// A state such as `"heating"`.
+    String(String),
+    /// A numeric series such as a spectrum.
+    Array(Vec<f64>),
+}
+
+impl MetricValue {
+    /// Numeric view for sinks that only store numbers; booleans become 0/1.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            MetricValue::Bool(b) => Some(f64::from(u8::from(b))),
+            MetricValue::UInt(n) => Some(n as f64),
+            MetricValue::Int(n) => Some(n as f64),
+            MetricValue::Float(v) => Some(v),
+            MetricValue::String(_) | MetricValue::Array(_) => None,
+        }
+    }
+
+    /// No NaN/Inf, including inside arrays.
+    pub fn is_finite(&self) -> bool {
+        match self {
+            MetricValue::Float(v) => v.is_finite(),
+            MetricValue::Array(vs) => vs.iter().all(|v| v.is_finite()),
+            _ => true,
+        }
+    }
+
+    /// Parses a bare word: integers stay exact, then floats; `None` otherwise.
+    pub fn parse_number(s: &str) -> Option<Self> {
+        if let Ok(n) = s.parse::<u64>() {
+            return Some(MetricValue::UInt(n));
+        }
+        if let Ok(n) = s.parse::<i64>() {
+            return Some(MetricValue::Int(n));
+        }
+        s.parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .map(MetricValue::Float)
+    }
+}
+
+/// Compares numerically, so `metrics["temp_c"] == 21.5` reads naturally.
+impl PartialEq<f64> for MetricValue {
+    fn eq(&self, other: &f64) -> bool {
+        match self {
+            MetricValue::UInt(_) | MetricValue::Int(_) | MetricValue::Float(_) => {
+                self.as_f64() == Some(*other)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<f64> for MetricValue {
+    fn from(v: f64) -> Self {
+        MetricValue::Float(v)
+    }
+}
+
+impl From<bool> for MetricValue {
+    fn from(v: bool) -> Self {
+        MetricValue::Bool(v)
+    }
+}
+
+impl From<u64> for MetricValue {
+    fn from(v: u64) -> Self {
+        MetricValue::UInt(v)
+    }
+}
+
+impl From<i64> for MetricValue {
+    fn from(v: i64) -> Self {
+        MetricValue::Int(v)
+    }
+}
+
+impl From<String> for MetricValue {
+    fn from(v: String) -> Self {
+        MetricValue::String(v)
+    }
+}
+
+impl From<Vec<f64>> for MetricValue {
+    fn from(v: Vec<f64>) -> Self {
+        MetricValue::Array(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_numbers_and_typed_values_round_trip() {
+        let m: std::collections::BTreeMap<String, MetricValue> = serde_json::from_str(
+            r#"{"t":21.5,"n":3,"neg":-4,"big":18446744073709551615,"on":true,"mode":"heating","fft":[1,2.5]}"#,
+        )
+        .unwrap();
+        assert_eq!(m["t"], MetricValue::Float(21.5));
+        assert_eq!(m["n"], MetricValue::UInt(3));
+        assert_eq!(m["neg"], MetricValue::Int(-4));
+        assert_eq!(m["big"], MetricValue::UInt(u64::MAX));
+        assert_eq!(m["on"], MetricValue::Bool(true));
+        assert_eq!(m["mode"], MetricValue::String("heating".into()));
+        assert_eq!(m["fft"], MetricValue::Array(vec![1.0, 2.5]));
+
+        assert_eq!(
+            serde_json::to_string(&m["big"]).unwrap(),
+            "18446744073709551615"
+        );
+        assert!(serde_json::from_str::<MetricValue>(r#"{"a":1}"#).is_err());
+    }
+
+    #[test]
+    fn parses_bare_numbers_exactly() {
+        assert_eq!(
+            MetricValue::parse_number("9007199254740993"),
+            Some(MetricValue::UInt(9_007_199_254_740_993))
+        );
+        assert_eq!(MetricValue::parse_number("-2"), Some(MetricValue::Int(-2)));
+        assert_eq!(
+            MetricValue::parse_number("1e3"),
+            Some(MetricValue::Float(1000.0))
+        );
+        assert_eq!(MetricValue::parse_number("NaN"), None);
+        assert_eq!(MetricValue::parse_number("on"), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! gRPC `gateway.ingest.v1.Ingest` service from proto/ingest.proto, served on its own
+//! port next to the axum router. Written by hand like `ingest::proto` so the build
+//! doesn't need `protoc`; readings go through `pipeline::submit` like HTTP ingest.
+
+use prost::Message;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tonic::codegen::{Body, Service, StdError, http};
+use tonic::server::{ClientStreamingService, Grpc, NamedService, UnaryService};
+use tonic::{Code, Request, Response, Status, Streaming};
+use tonic_prost::ProstCodec;
+
+use crate::app::AppState;
+use crate::config::GrpcCfg;
+use crate::ingest::pipeline::{self, Accepted, IngestError};
+use crate::ingest::proto::{BatchReply, IngestBatch, IngestReply, ItemResult, ItemStatus, Reading};
+
+/// Request metadata naming the device for readings without `device_id`.
+const DEVICE_ID_METADATA: &str = "x-device-id";
+/// Stream readings refused for backpressure are retried this often before being rejected.
+const STREAM_RETRY_DELAY: Duration = Duration::from_millis(100);
+const STREAM_RETRIES: u32 = 20;
+
+type ReplyFuture<T> = Pin<Box<dyn Future<Output = Result<Response<T>, Status>> + Send>>;
+
+pub async fn serve(cfg: GrpcCfg, st: AppState) -> anyhow::Result<()> {
+    tracing::info!(addr = %cfg.bind, "grpc listening");
+    tonic::transport::Server::builder()
+        .add_service(IngestServer { st })
+        .serve(cfg.bind)
+        .await?;
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct IngestServer {
+    st: AppState,
+}
+
+impl NamedService for IngestServer {
+    const NAME: &'static str = "gateway.ingest.v1.Ingest";
+}
+
+impl<B> Service<http::Request<B>> for IngestServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let st = self.st.clone();
+        let (max_payload, max_batch) = (
+            st.cfg.ingest.max_payload_bytes,
+            st.cfg.ingest.max_batch_bytes,
+        );
+        match req.uri().path() {
+            "/gateway.ingest.v1.Ingest/Ingest" => Box::pin(async move {
+                let mut grpc =
+                    Grpc::new(ProstCodec::default()).max_decoding_message_size(max_payload);
+                Ok(grpc.unary(IngestSvc(st), req).await)
+            }),
+            "/gateway.ingest.v1.Ingest/IngestBatch" => Box::pin(async move {
+                let mut grpc =
+                    Grpc::new(ProstCodec::default()).max_decoding_message_size(max_batch);
+                Ok(grpc.unary(IngestBatchSvc(st), req).await)
+            }),
+            "/gateway.ingest.v1.Ingest/IngestStream" => Box::pin(async move {
+                let mut grpc =
+                    Grpc::new(ProstCodec::default()).max_decoding_message_size(max_payload);
+                Ok(grpc.client_streaming(IngestStreamSvc(st), req).await)
+            }),
+            _ => Box::pin(async { Ok(Status::unimplemented("").into_http()) }),
+        }
+    }
+}
+
+struct IngestSvc(AppState);
+
+impl UnaryService<Reading> for IngestSvc {
+    type Response = IngestReply;
+    type Future = ReplyFuture<IngestReply>;
+
+    fn call(&mut self, req: Request<Reading>) -> Self::Future {
+        let st = self.0.clone();
+        Box::pin(async move { observe(&st, "Ingest", ingest(&st, req)) })
+    }
+}
+
+struct IngestBatchSvc(AppState);
+
+impl UnaryService<IngestBatch> for IngestBatchSvc {
+    type Response = BatchReply;
+    type Future = ReplyFuture<BatchReply>;
+
+    fn call(&mut self, req: Request<IngestBatch>) -> Self::Future {
+        let st = self.0.clone();
+        Box::pin(async move { observe(&st, "IngestBatch", ingest_batch(&st, req)) })
+    }
+}
+
+struct IngestStreamSvc(AppState);
+
+impl ClientStreamingService<Reading> for IngestStreamSvc {
+    type Response = BatchReply;
+    type Future = ReplyFuture<BatchReply>;
+
+    fn call(&mut self, req: Request<Streaming<Reading>>) -> Self::Future {
+        let st = self.0.clone();
+        Box::pin(async move {
+            let res = ingest_stream(&st, req).await;
+            observe(&st, "IngestStream", res)
+        })
+    }
+}
+
+fn observe<T>(st: &AppState, method: &'static str, res: Result<T, Status>) -> Result<T, Status> {
+    let code = res.as_ref().err().map_or(Code::Ok, Status::code);
+    st.metrics.grpc_request(method, code);
+    res
+}
+
+fn ingest(st: &AppState, req: Request<Reading>) -> Result<Response<IngestReply>, Status> {
+    let fallback = metadata_device_id(&req);
+    let accepted = submit_reading(st, req.into_inner(), fallback.as_deref()).map_err(status)?;
+    Ok(Response::new(IngestReply {
+        status: item_status(&Ok(accepted)) as i32,
+        event_id: event_id(&Ok(accepted)),
+    }))
+}
+
+fn ingest_batch(st: &AppState, req: Request<IngestBatch>) -> Result<Response<BatchReply>, Status> {
+    let fallback = metadata_device_id(&req);
+    let batch = req.into_inner();
+    if batch.readings.len() > st.cfg.ingest.max_batch_items {
+        return Err(Status::invalid_argument("too_many_items"));
+    }
+    if !st.ready.is_ready(&st.cfg.health) {
+        return Err(status(IngestError::NotReady));
+    }
+
+    let mut reply = BatchReply::default();
+    for (index, reading) in batch.readings.into_iter().enumerate() {
+        let outcome = submit_reading(st, reading, fallback.as_deref());
+        record(&mut reply, index, outcome, true);
+    }
+    Ok(Response::new(reply))
+}
+
+async fn ingest_stream(
+    st: &AppState,
+    req: Request<Streaming<Reading>>,
+) -> Result<Response<BatchReply>, Status> {
+    let fallback = metadata_device_id(&req);
+    let mut stream = req.into_inner();
+    let mut reply = BatchReply::default();
+    let mut index = 0;
+    while let Some(reading) = stream.message().await? {
+        let mut attempt = 0;
+        let outcome = loop {
+            match submit_reading(st, reading.clone(), fallback.as_deref()) {
+                Err(IngestError::QueueFull | IngestError::InflightBytes)
+                    if attempt < STREAM_RETRIES =>
+                {
+                    attempt += 1;
+                    tokio::time::sleep(STREAM_RETRY_DELAY).await;
+                }
+                outcome => break outcome,
+            }
+        };
+        record(&mut reply, index, outcome, false);
+        index += 1;
+    }
+    Ok(Response::new(reply))
+}
+
+fn metadata_device_id<T>(req: &Request<T>) -> Option<String> {
+    req.metadata()
+        .get(DEVICE_ID_METADATA)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn submit_reading(
+    st: &AppState,
+    reading: Reading,
+    fallback_device_id: Option<&str>,
+) -> Result<Accepted, IngestError> {
+    let bytes = reading.encoded_len();
+    let item = reading.into_batch_item().map_err(|reason| {
+        st.metrics.ingest_rejected_total(reason);
+        IngestError::Invalid(reason)
+    })?;
+    let Some(device_id) = item.device_id.as_deref().or(fallback_device_id) else {
+        st.metrics.ingest_rejected_total("missing_device_id");
+        return Err(IngestError::Invalid("missing_device_id"));
+    };
+    pipeline::submit(st, device_id, item.body, bytes)
+}
+
+fn item_status(outcome: &Result<Accepted, IngestError>) -> ItemStatus {
+    match outcome {
+        Ok(Accepted::Enqueued(_)) => ItemStatus::Accepted,
+        Ok(Accepted::Duplicate) => ItemStatus::Duplicate,
+        Err(_) => ItemStatus::Rejected,
+    }
+}
+
+/// Id of the new event as a string; empty for duplicates and rejections.
+fn event_id(outcome: &Result<Accepted, IngestError>) -> String {
+    match outcome {
+        Ok(Accepted::Enqueued(id)) => id.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Counts the outcome; `all` keeps a result for accepted readings too.
+fn record(reply: &mut BatchReply, index: usize, outcome: Result<Accepted, IngestError>, all: bool) {
+    let status = item_status(&outcome);
+    match status {
+        ItemStatus::Accepted => reply.accepted += 1,
+        ItemStatus::Duplicate => reply.duplicates += 1,
+        _ => reply.rejected += 1,
+    }
+    if all || status != ItemStatus::Accepted {
+        reply.results.push(ItemResult {
+            index: index as u32,
+            status: status as i32,
+            event_id: Some(event_id(&outcome)).filter(|id| !id.is_empty()),
+            code: outcome.err().map(|e| e.code().to_string()),
+        });
+    }
+}
+
+fn status(e: IngestError) -> Status {
+    match e {
+        IngestError::Invalid(reason) => Status::invalid_argument(reason),
+        IngestError::NotReady => Status::unavailable(e.code()),
+        _ => Status::resource_exhausted(e.code()),
+    }
+}
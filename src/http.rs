@@ -1,7 +1,7 @@
 use axum::http::{self, StatusCode};
 use axum::{
     Extension, Router,
-    extract::State,
+    extract::{DefaultBodyLimit, State},
     response::IntoResponse,
     routing::{get, post},
 };
@@ -19,18 +19,56 @@ use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::app::AppState;
+use crate::clock::ClockTracker;
 use crate::config::GatewayGfg;
 use crate::dispatcher::Dispatcher;
 use crate::domain::Event;
 use crate::fanout::FanoutSink;
-use crate::ingest::types::IngestBody;
+use crate::ingest::budget::ByteBudget;
+use crate::ingest::decoder::PayloadDecoders;
+use crate::ingest::prometheus::SeriesLimiter;
+use crate::ingest::types::{BatchItem, BatchResponse, ErrorBody, IngestAck, IngestBody};
+use crate::ingest::ws::WsAck;
 use crate::readiness::{self, Readiness, start_readisness_probes};
+use crate::registry::{MetricDescriptor, MetricRegistry};
+use crate::sequence::{ReorderBuffer, SeqTracker};
+use crate::timesync::{SyncReport, TimeResponse};
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(crate::ingest::handler::ingest),
-    components(schemas(IngestBody)),
-    tags((name = "ingest", description = "Device data ingestion"))
+    paths(
+        crate::ingest::handler::ingest,
+        crate::ingest::batch::ingest_batch,
+        crate::ingest::batch::ingest_batch_multi,
+        crate::ingest::senml::ingest_senml,
+        crate::ingest::senml::ingest_senml_by_base_name,
+        crate::ingest::ws::ingest_ws,
+        crate::ingest::influx::write,
+        crate::ingest::otlp::export_metrics,
+        crate::ingest::prometheus::ingest_prometheus,
+        crate::ingest::lorawan::tts_uplink,
+        crate::ingest::lorawan::chirpstack_event,
+        crate::timesync::time,
+        crate::timesync::report_sync,
+        crate::registry::list_descriptors,
+        crate::registry::get_descriptor
+    ),
+    components(schemas(
+        IngestBody,
+        IngestAck,
+        BatchItem,
+        BatchResponse,
+        ErrorBody,
+        TimeResponse,
+        SyncReport,
+        WsAck,
+        MetricDescriptor
+    )),
+    tags(
+        (name = "ingest", description = "Device data ingestion"),
+        (name = "time", description = "Time sync for devices without an RTC"),
+        (name = "metadata", description = "What metric names mean")
+    )
 )]
 pub struct ApiDoc;
 
@@ -39,6 +77,7 @@ struct HealthReport {
     accepting: bool,
     disk_ok: bool,
     mqtt_ok: bool,
+    broker_clients: usize,
 }
 
 pub async fn serve(addr: std::net::SocketAddr, cfg: Arc<GatewayGfg>) -> anyhow::Result<()> {
@@ -52,16 +91,126 @@ pub async fn serve(addr: std::net::SocketAddr, cfg: Arc<GatewayGfg>) -> anyhow::
 
     let fanout = Arc::new(FanoutSink::new(vec![]));
 
-    tokio::spawn(Dispatcher::new(rx, fanout.clone()).run());
+    let inflight = Arc::new(ByteBudget::new(cfg.ingest.max_inflight_bytes));
+    let mut dispatcher = Dispatcher::new(rx, fanout.clone(), inflight.clone(), app_metrics.clone());
+    if cfg.sequence.reorder {
+        dispatcher = dispatcher.with_reorder(ReorderBuffer::new(
+            &cfg.sequence,
+            cfg.ingest.max_tracked_devices,
+            app_metrics.clone(),
+        ));
+    }
+    tokio::spawn(dispatcher.run());
 
     let state = AppState {
         cfg: cfg.clone(),
         ready: readiness.clone(),
         ingest_tx: tx,
+        inflight,
         metrics: app_metrics.clone(),
+        seq: Arc::new(SeqTracker::new(
+            &cfg.sequence,
+            cfg.ingest.max_tracked_devices,
+        )),
+        clock: Arc::new(ClockTracker::new(
+            &cfg.clock,
+            cfg.ingest.max_tracked_devices,
+        )),
+        series: Arc::new(SeriesLimiter::new(
+            cfg.ingest.prometheus_max_series,
+            cfg.ingest.max_tracked_devices,
+        )),
+        decoders: Arc::new(PayloadDecoders::new(&cfg.decoders, app_metrics.clone())),
+        registry: Arc::new(MetricRegistry::new(&cfg.metric_descriptors)),
     };
 
+    if cfg.coap.enabled {
+        let (coap_cfg, st) = (cfg.coap.clone(), state.clone());
+        tokio::spawn(async move {
+            if let Err(e) = crate::coap::serve(coap_cfg, st).await {
+                tracing::error!(error = %e, "coap listener stopped");
+            }
+        });
+    }
+
+    if cfg.broker.enabled {
+        let (broker_cfg, st) = (cfg.broker.clone(), state.clone());
+        tokio::spawn(async move {
+            if let Err(e) = crate::broker::serve(broker_cfg, st).await {
+                tracing::error!(error = %e, "mqtt broker stopped");
+            }
+        });
+    }
+
+    if cfg.grpc.enabled {
+        let (grpc_cfg, st) = (cfg.grpc.clone(), state.clone());
+        tokio::spawn(async move {
+            if let Err(e) = crate::grpc::serve(grpc_cfg, st).await {
+                tracing::error!(error = %e, "grpc server stopped");
+            }
+        });
+    }
+
+    let lines_cfg = Arc::new(cfg.lines.clone());
+    if let Some(bind) = cfg.lines.tcp_bind {
+        let (lines_cfg, st) = (lines_cfg.clone(), state.clone());
+        tokio::spawn(async move {
+            if let Err(e) = crate::lines::serve_tcp(bind, lines_cfg, st).await {
+                tracing::error!(error = %e, "tcp line listener stopped");
+            }
+        });
+    }
+    if let Some(bind) = cfg.lines.udp_bind {
+        let (lines_cfg, st) = (lines_cfg.clone(), state.clone());
+        tokio::spawn(async move {
+            if let Err(e) = crate::lines::serve_udp(bind, lines_cfg, st).await {
+                tracing::error!(error = %e, "udp line listener stopped");
+            }
+        });
+    }
+
+    for modbus_cfg in &cfg.modbus {
+        tokio::spawn(crate::modbus::run(modbus_cfg.clone(), state.clone()));
+    }
+
+    for serial_cfg in &cfg.serial {
+        crate::serial::spawn(serial_cfg.clone(), state.clone());
+    }
+
+    if cfg.mqtt_ingress.enabled {
+        tokio::spawn(crate::mqtt_ingress::run(
+            cfg.mqtt_ingress.clone(),
+            state.clone(),
+        ));
+    }
+
     let openapi = ApiDoc::openapi();
+    // Batch routes get their own, larger body limit.
+    let batch = Router::new()
+        .route(
+            "/v1/ingest/batch",
+            post(crate::ingest::batch::ingest_batch_multi),
+        )
+        .route(
+            "/v1/ingest/:device_id/batch",
+            post(crate::ingest::batch::ingest_batch),
+        )
+        .route(
+            "/v1/ingest/senml",
+            post(crate::ingest::senml::ingest_senml_by_base_name),
+        )
+        .route(
+            "/v1/ingest/:device_id/senml",
+            post(crate::ingest::senml::ingest_senml),
+        )
+        .route("/api/v2/write", post(crate::ingest::influx::write))
+        .route("/v1/metrics", post(crate::ingest::otlp::export_metrics))
+        .route(
+            "/v1/ingest/:device_id/prometheus",
+            post(crate::ingest::prometheus::ingest_prometheus),
+        )
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(cfg.ingest.max_batch_bytes));
 
     let app = Router::new()
         .merge(SwaggerUi::new("/docs").url("/docs/openapi.json", openapi))
@@ -69,13 +218,40 @@ pub async fn serve(addr: std::net::SocketAddr, cfg: Arc<GatewayGfg>) -> anyhow::
         .route("/readyz", get(readyz))
         .route(
             "/v1/ingest/:device_id",
-            post(crate::ingest::handler::ingest),
+            post(crate::ingest::handler::ingest)
+                .layer(RequestBodyLimitLayer::new(cfg.ingest.max_payload_bytes)),
+        )
+        .route(
+            "/v1/ingest/:device_id/ws",
+            get(crate::ingest::ws::ingest_ws),
+        )
+        .route(
+            "/v1/lorawan/tts/uplink",
+            post(crate::ingest::lorawan::tts_uplink)
+                .layer(RequestBodyLimitLayer::new(cfg.ingest.max_payload_bytes)),
+        )
+        .route(
+            "/v1/lorawan/chirpstack",
+            post(crate::ingest::lorawan::chirpstack_event)
+                .layer(RequestBodyLimitLayer::new(cfg.ingest.max_payload_bytes)),
+        )
+        .merge(batch)
+        .route("/v1/time", get(crate::timesync::time))
+        .route("/v1/time/:device_id", post(crate::timesync::report_sync))
+        .route("/v1/descriptors", get(crate::registry::list_descriptors))
+        .route(
+            "/v1/descriptors/:name",
+            get(crate::registry::get_descriptor),
+        )
+        .route("/admin/sequence", get(crate::admin::sequence_reports))
+        .route(
+            "/admin/sequence/:device_id",
+            get(crate::admin::sequence_report),
         )
+        .route("/admin/clock", get(crate::admin::clock_reports))
+        .route("/admin/clock/:device_id", get(crate::admin::clock_report))
         .route("/metrics", get(|| async move { prom_handle.render() }))
         .with_state(state.clone())
-        .layer(RequestBodyLimitLayer::new(
-            state.cfg.ingest.max_payload_bytes,
-        ))
         .layer(Extension(readiness.clone()))
         .layer(Extension(cfg.clone()))
         .layer(prom_layer)
@@ -95,7 +271,7 @@ pub async fn serve(addr: std::net::SocketAddr, cfg: Arc<GatewayGfg>) -> anyhow::
                       "response"
                     )
                 })
-                .on_failure(|error: _, latency: Duration, _span: &Span| {
+                .on_failure(|_error: _, latency: Duration, _span: &Span| {
                     tracing::warn!(latency_ms = %latency.as_millis(), "request_failed");
                 }),
         );
@@ -122,8 +298,10 @@ pub async fn shutdown_signal(readiness: Arc<Readiness>) {
     readiness.set_accepting(false);
     readiness.disk_ok.store(false, Ordering::Relaxed);
     readiness.mqtt_ok.store(false, Ordering::Relaxed);
+    readiness.broker_ok.store(false, Ordering::Relaxed);
 
     tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    readiness.stop();
 }
 
 #[tracing::instrument(skip_all, fields(kind = "health"))]
@@ -136,6 +314,7 @@ async fn healthz(
         accepting: true,
         disk_ok: r.disk_ok.load(Ordering::Relaxed),
         mqtt_ok: r.mqtt_ok.load(Ordering::Relaxed),
+        broker_clients: r.broker_clients.load(Ordering::Relaxed),
     };
     (StatusCode::OK, axum::Json(report))
 }
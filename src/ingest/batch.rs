@@ -0,0 +1,238 @@
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use prost::Message;
+use serde_json::value::RawValue;
+
+use crate::app::AppState;
+use crate::ingest::encoding::decode_body;
+use crate::ingest::handler::content_type;
+use crate::ingest::pipeline::{self, Accepted, IngestError};
+use crate::ingest::proto;
+use crate::ingest::types::{BatchItem, BatchResponse, IngestBody, ItemResult, ItemStatus};
+
+/// One not-yet-validated batch entry.
+enum RawItem<'a> {
+    Json(&'a [u8]),
+    Proto(proto::Reading),
+}
+
+impl RawItem<'_> {
+    /// Size of the item on the wire, used for `Event::bytes`.
+    fn len(&self) -> usize {
+        match self {
+            RawItem::Json(raw) => raw.len(),
+            RawItem::Proto(reading) => reading.encoded_len(),
+        }
+    }
+
+    fn parse(self, st: &AppState) -> Result<BatchItem, IngestError> {
+        let parsed = match self {
+            RawItem::Json(raw) => serde_json::from_slice(raw).map_err(|_| "invalid_item"),
+            RawItem::Proto(reading) => reading.into_batch_item(),
+        };
+        parsed.map_err(|reason| {
+            st.metrics.ingest_rejected_total(reason);
+            IngestError::Invalid(reason)
+        })
+    }
+}
+
+/// Split a batch body into items: a JSON array, `application/x-ndjson` lines,
+/// or a protobuf `IngestBatch`. Items are parsed individually later so one bad
+/// item doesn't fail the whole batch.
+fn split_items<'a>(
+    headers: &HeaderMap,
+    raw: &'a [u8],
+    max_items: usize,
+) -> Result<Vec<RawItem<'a>>, StatusCode> {
+    let mime = content_type(headers).map(str::to_ascii_lowercase);
+    let items: Vec<RawItem> = match mime.as_deref() {
+        Some("application/json") => serde_json::from_slice::<Vec<&RawValue>>(raw)
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+            .into_iter()
+            .map(|v| RawItem::Json(v.get().as_bytes()))
+            .collect(),
+        Some("application/x-ndjson" | "application/jsonl") => raw
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.trim_ascii().is_empty())
+            .map(RawItem::Json)
+            .collect(),
+        Some("application/x-protobuf" | "application/protobuf") => proto::IngestBatch::decode(raw)
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+            .readings
+            .into_iter()
+            .map(RawItem::Proto)
+            .collect(),
+        _ => return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+    };
+    if items.len() > max_items {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    Ok(items)
+}
+
+pub(crate) fn respond(st: &AppState, outcomes: Vec<Result<Accepted, IngestError>>) -> Response {
+    let mut resp = BatchResponse::default();
+    for (index, outcome) in outcomes.into_iter().enumerate() {
+        let (status, event_id, code) = match outcome {
+            Ok(Accepted::Enqueued(id)) => {
+                resp.accepted += 1;
+                (ItemStatus::Accepted, Some(id), None)
+            }
+            Ok(Accepted::Duplicate) => {
+                resp.duplicates += 1;
+                (ItemStatus::Duplicate, None, None)
+            }
+            Err(e) => {
+                resp.rejected += 1;
+                (ItemStatus::Rejected, None, Some(e.code().to_string()))
+            }
+        };
+        resp.results.push(ItemResult {
+            index,
+            status,
+            event_id,
+            code,
+        });
+    }
+    (pipeline::ack_status(st), Json(resp)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/ingest/{device_id}/batch",
+    request_body(
+        content = Vec<IngestBody>,
+        description = "JSON array, one JSON object per line with `application/x-ndjson`, or a protobuf `IngestBatch` with `application/x-protobuf`",
+        content_type = "application/json"
+    ),
+    params(
+        ("device_id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 202, description = "Batch processed; see per-item results", body = BatchResponse),
+        (status = 400, description = "Body is not a JSON array"),
+        (status = 413, description = "Too many items or body too large"),
+        (status = 415, description = "Unsupported Content-Type or Content-Encoding"),
+        (status = 503, description = "Not accepting"),
+    ),
+    tag = "ingest"
+)]
+pub async fn ingest_batch(
+    State(st): State<AppState>,
+    Path(device_id): Path<String>,
+    headers: HeaderMap,
+    raw: Bytes,
+) -> Response {
+    let raw = match decode_body(&st.metrics, &headers, raw, st.cfg.ingest.max_batch_bytes) {
+        Ok(raw) => raw,
+        Err(status) => return status.into_response(),
+    };
+    let items = match split_items(&headers, &raw, st.cfg.ingest.max_batch_items) {
+        Ok(items) => items,
+        Err(status) => return status.into_response(),
+    };
+    if !st.ready.is_ready(&st.cfg.health) {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let outcomes = items
+        .into_iter()
+        .map(|item| {
+            let bytes = item.len();
+            // Any per-item device_id is ignored; the path decides.
+            let BatchItem { body, .. } = item.parse(&st)?;
+            pipeline::submit(&st, &device_id, body, bytes)
+        })
+        .collect();
+    respond(&st, outcomes)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/ingest/batch",
+    request_body(
+        content = Vec<BatchItem>,
+        description = "JSON array, one JSON object per line with `application/x-ndjson`, or a protobuf `IngestBatch` with `application/x-protobuf`; each item carries its `device_id`",
+        content_type = "application/json"
+    ),
+    responses(
+        (status = 202, description = "Batch processed; see per-item results", body = BatchResponse),
+        (status = 400, description = "Body is not a JSON array"),
+        (status = 413, description = "Too many items or body too large"),
+        (status = 415, description = "Unsupported Content-Type or Content-Encoding"),
+        (status = 503, description = "Not accepting"),
+    ),
+    tag = "ingest"
+)]
+pub async fn ingest_batch_multi(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    raw: Bytes,
+) -> Response {
+    let raw = match decode_body(&st.metrics, &headers, raw, st.cfg.ingest.max_batch_bytes) {
+        Ok(raw) => raw,
+        Err(status) => return status.into_response(),
+    };
+    let items = match split_items(&headers, &raw, st.cfg.ingest.max_batch_items) {
+        Ok(items) => items,
+        Err(status) => return status.into_response(),
+    };
+    if !st.ready.is_ready(&st.cfg.health) {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let outcomes = items
+        .into_iter()
+        .map(|item| {
+            let bytes = item.len();
+            let BatchItem { device_id, body } = item.parse(&st)?;
+            let Some(device_id) = device_id else {
+                st.metrics.ingest_rejected_total("missing_device_id");
+                return Err(IngestError::Invalid("missing_device_id"));
+            };
+            pipeline::submit(&st, &device_id, body, bytes)
+        })
+        .collect();
+    respond(&st, outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderValue, header};
+
+    fn headers(ct: &'static str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert(header::CONTENT_TYPE, HeaderValue::from_static(ct));
+        h
+    }
+
+    #[test]
+    fn splits_json_array_and_ndjson() {
+        let arr = br#"[{"seq":1}, {"seq":"bad"}, {"seq":3}]"#;
+        let items = split_items(&headers("application/json"), arr, 10).unwrap();
+        let lens: Vec<_> = items.iter().map(RawItem::len).collect();
+        assert_eq!(lens, vec![9, 13, 9]);
+
+        let nd = b"{\"seq\":1}\n\n{\"seq\":2}\r\n";
+        let items = split_items(&headers("application/x-ndjson"), nd, 10).unwrap();
+        assert_eq!(items.len(), 2);
+    }
+
+    #[test]
+    fn enforces_item_limit_and_media_type() {
+        let arr = br#"[{}, {}, {}]"#;
+        assert_eq!(
+            split_items(&headers("application/json"), arr, 2).err(),
+            Some(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        assert_eq!(
+            split_items(&headers("text/plain"), arr, 10).err(),
+            Some(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+        );
+    }
+}
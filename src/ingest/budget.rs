@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bounds the total payload bytes of events sitting in the pipeline
+/// (queue + reorder buffer) so bursts of large bodies can't exhaust RAM.
+pub struct ByteBudget {
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl ByteBudget {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reserve `n` bytes; false when that would exceed the budget.
+    pub fn try_acquire(&self, n: usize) -> bool {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(n).filter(|&total| total <= self.max)
+            })
+            .is_ok()
+    }
+
+    pub fn release(&self, n: usize) {
+        self.used.fetch_sub(n, Ordering::AcqRel);
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_over_budget_until_released() {
+        let b = ByteBudget::new(100);
+        assert!(b.try_acquire(60));
+        assert!(!b.try_acquire(50));
+        b.release(60);
+        assert!(b.try_acquire(100));
+        assert_eq!(b.used(), 100);
+    }
+}
@@ -0,0 +1,268 @@
+//! Config-defined decoders for packed binary payloads (`[[decoders]]`). A blob
+//! arrives base64 encoded in the JSON `payload`, or as a raw
+//! `application/octet-stream` body, and is expanded into named metrics.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::config::{DecoderCfg, DecoderFieldCfg, Endian, FieldType};
+use crate::domain::MetricValue;
+use crate::ingest::types::IngestBody;
+use crate::metrics::AppMetrics;
+
+pub struct PayloadDecoders {
+    decoders: Vec<DecoderCfg>,
+    by_device: HashMap<String, usize>,
+    metrics: Arc<AppMetrics>,
+}
+
+impl PayloadDecoders {
+    pub fn new(cfgs: &[DecoderCfg], metrics: Arc<AppMetrics>) -> Self {
+        let mut by_device = HashMap::new();
+        for (i, d) in cfgs.iter().enumerate() {
+            for device in &d.devices {
+                by_device.entry(device.clone()).or_insert(i);
+            }
+        }
+        Self {
+            decoders: cfgs.to_vec(),
+            by_device,
+            metrics,
+        }
+    }
+
+    /// The decoder listing the device, else the first whose tags all match.
+    fn select(&self, device_id: &str, tags: &BTreeMap<String, String>) -> Option<&DecoderCfg> {
+        if let Some(&i) = self.by_device.get(device_id) {
+            return Some(&self.decoders[i]);
+        }
+        self.decoders
+            .iter()
+            .find(|d| !d.tags.is_empty() && d.tags.iter().all(|(k, v)| tags.get(k) == Some(v)))
+    }
+
+    /// Expands a base64 blob in `body.payload` into `body.metrics` when a decoder
+    /// applies to the event. Bodies without a blob are left alone.
+    pub fn apply(&self, device_id: &str, body: &mut IngestBody) -> Result<(), &'static str> {
+        let Some(decoder) = self.select(device_id, &body.tags) else {
+            return Ok(());
+        };
+        let blob = match &decoder.payload_field {
+            None => body.payload.as_str(),
+            Some(field) => body.payload.get(field).and_then(|v| v.as_str()),
+        };
+        let Some(blob) = blob else {
+            return Ok(());
+        };
+        let decoded = STANDARD
+            .decode(blob)
+            .map_err(|_| "invalid_base64")
+            .and_then(|bytes| decode(decoder, &bytes));
+        self.record(decoder, &decoded);
+        body.metrics.extend(decoded?);
+        Ok(())
+    }
+
+    /// Builds a body from a raw binary blob; `None` when no decoder lists the device.
+    pub fn decode_raw(
+        &self,
+        device_id: &str,
+        blob: &[u8],
+    ) -> Option<Result<IngestBody, &'static str>> {
+        let decoder = &self.decoders[*self.by_device.get(device_id)?];
+        Some(self.decode_body(decoder, blob))
+    }
+
+    /// Like [`Self::decode_raw`] with the decoder chosen by name; `None` if unknown.
+    pub fn decode_named(
+        &self,
+        name: &str,
+        blob: &[u8],
+    ) -> Option<Result<IngestBody, &'static str>> {
+        let decoder = self.decoders.iter().find(|d| d.name == name)?;
+        Some(self.decode_body(decoder, blob))
+    }
+
+    fn decode_body(&self, decoder: &DecoderCfg, blob: &[u8]) -> Result<IngestBody, &'static str> {
+        let decoded = decode(decoder, blob);
+        self.record(decoder, &decoded);
+        decoded.map(|metrics| IngestBody {
+            ts: None,
+            seq: None,
+            metrics,
+            tags: BTreeMap::new(),
+            units: BTreeMap::new(),
+            payload: serde_json::Value::Null,
+        })
+    }
+
+    fn record<T>(&self, decoder: &DecoderCfg, result: &Result<T, &'static str>) {
+        let result = match result {
+            Ok(_) => "ok",
+            Err(reason) => reason,
+        };
+        self.metrics.payload_decoded(&decoder.name, result);
+    }
+}
+
+/// A field as read from the blob, before scale and offset.
+enum Raw {
+    Int(i64),
+    Float(f64),
+}
+
+fn read(field: &DecoderFieldCfg, blob: &[u8]) -> Option<Raw> {
+    let bytes = blob.get(field.offset..field.offset.checked_add(field.kind.size())?)?;
+    macro_rules! num {
+        ($t:ty) => {{
+            let arr = bytes.try_into().ok()?;
+            match field.endian {
+                Endian::Little => <$t>::from_le_bytes(arr),
+                Endian::Big => <$t>::from_be_bytes(arr),
+            }
+        }};
+    }
+    let raw = match field.kind {
+        FieldType::U8 => Raw::Int(i64::from(bytes[0])),
+        FieldType::I8 => Raw::Int(i64::from(bytes[0] as i8)),
+        FieldType::U16 => Raw::Int(i64::from(num!(u16))),
+        FieldType::I16 => Raw::Int(i64::from(num!(i16))),
+        FieldType::U32 => Raw::Int(i64::from(num!(u32))),
+        FieldType::I32 => Raw::Int(i64::from(num!(i32))),
+        FieldType::F32 => Raw::Float(f64::from(num!(f32))),
+        FieldType::F64 => Raw::Float(num!(f64)),
+    };
+    Some(raw)
+}
+
+/// `v` as an `i64` if it is a whole number in range.
+fn whole(v: f64) -> Option<i64> {
+    (v.fract() == 0.0 && v.abs() < i64::MAX as f64).then_some(v as i64)
+}
+
+/// Reads one field from `blob` and applies its scale and offset. Integer fields
+/// with a whole scale and offset stay integers.
+pub(crate) fn decode_field(
+    field: &DecoderFieldCfg,
+    blob: &[u8],
+) -> Result<MetricValue, &'static str> {
+    let raw = read(field, blob).ok_or("payload_too_short")?;
+    let raw = match raw {
+        Raw::Int(n) => match (whole(field.scale), whole(field.value_offset)) {
+            (Some(scale), Some(offset)) => {
+                return n
+                    .checked_mul(scale)
+                    .and_then(|v| v.checked_add(offset))
+                    .map(MetricValue::Int)
+                    .ok_or("value_out_of_range");
+            }
+            _ => n as f64,
+        },
+        Raw::Float(v) => v,
+    };
+    let v = raw * field.scale + field.value_offset;
+    if !v.is_finite() {
+        return Err("non_finite_value");
+    }
+    Ok(MetricValue::Float(v))
+}
+
+/// Decodes every field of `decoder` from `blob`.
+pub fn decode(
+    decoder: &DecoderCfg,
+    blob: &[u8],
+) -> Result<BTreeMap<String, MetricValue>, &'static str> {
+    decoder
+        .fields
+        .iter()
+        .map(|f| Ok((f.name.clone(), decode_field(f, blob)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, offset: usize, kind: FieldType, endian: Endian) -> DecoderFieldCfg {
+        DecoderFieldCfg {
+            name: name.into(),
+            offset,
+            kind,
+            endian,
+            scale: 1.0,
+            value_offset: 0.0,
+        }
+    }
+
+    fn env_decoder() -> DecoderCfg {
+        let mut temp = field("temp_c", 1, FieldType::I16, Endian::Little);
+        temp.scale = 0.01;
+        DecoderCfg {
+            name: "env_v1".into(),
+            devices: vec!["dev1".into()],
+            tags: BTreeMap::from([("fw".into(), "env-1".into())]),
+            payload_field: None,
+            fields: vec![
+                field("status", 0, FieldType::U8, Endian::Little),
+                temp,
+                field("pressure", 3, FieldType::F32, Endian::Big),
+            ],
+        }
+    }
+
+    // struct __packed { uint8_t status; int16_t temp_c_x100; float pressure_be; }
+    fn blob() -> Vec<u8> {
+        let mut b = vec![7];
+        b.extend_from_slice(&(-1234i16).to_le_bytes());
+        b.extend_from_slice(&1013.25f32.to_be_bytes());
+        b
+    }
+
+    #[test]
+    fn decodes_packed_struct() {
+        let m = decode(&env_decoder(), &blob()).unwrap();
+        assert_eq!(m["status"], MetricValue::Int(7));
+        assert!(matches!(m["temp_c"], MetricValue::Float(v) if (v + 12.34).abs() < 1e-9));
+        assert_eq!(m["pressure"], MetricValue::Float(1013.25));
+
+        let mut counter = field("count", 0, FieldType::U32, Endian::Big);
+        counter.scale = 10.0;
+        counter.value_offset = -40.0;
+        let m = decode_field(&counter, &u32::MAX.to_be_bytes()).unwrap();
+        assert_eq!(m, MetricValue::Int(i64::from(u32::MAX) * 10 - 40));
+
+        assert_eq!(
+            decode(&env_decoder(), &blob()[..4]).unwrap_err(),
+            "payload_too_short"
+        );
+    }
+
+    #[test]
+    fn applies_to_base64_payload_by_device_or_tag() {
+        let decoders = PayloadDecoders::new(&[env_decoder()], AppMetrics::new());
+        let mut body = IngestBody {
+            ts: None,
+            seq: None,
+            metrics: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            units: BTreeMap::new(),
+            payload: STANDARD.encode(blob()).into(),
+        };
+
+        decoders.apply("other", &mut body).unwrap();
+        assert!(body.metrics.is_empty());
+
+        body.tags.insert("fw".into(), "env-1".into());
+        decoders.apply("other", &mut body).unwrap();
+        assert_eq!(body.metrics["status"], 7.0);
+
+        body.payload = "not base64!".into();
+        assert_eq!(decoders.apply("dev1", &mut body), Err("invalid_base64"));
+
+        assert!(decoders.decode_raw("other", &blob()).is_none());
+        let raw = decoders.decode_raw("dev1", &blob()).unwrap().unwrap();
+        assert_eq!(raw.metrics.len(), 3);
+    }
+}
@@ -0,0 +1,172 @@
+use axum::body::Bytes;
+use axum::http::{HeaderMap, StatusCode, header};
+use std::io::Read;
+
+use crate::metrics::AppMetrics;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Gzip,
+    Deflate,
+    Zstd,
+}
+
+impl Encoding {
+    fn from_headers(headers: &HeaderMap) -> Result<Option<Self>, StatusCode> {
+        let Some(v) = headers.get(header::CONTENT_ENCODING) else {
+            return Ok(None);
+        };
+        let v = v.to_str().map_err(|_| StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+        match v.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Ok(None),
+            "gzip" | "x-gzip" => Ok(Some(Encoding::Gzip)),
+            "deflate" => Ok(Some(Encoding::Deflate)),
+            "zstd" => Ok(Some(Encoding::Zstd)),
+            _ => Err(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+/// Read at most `limit` decoded bytes; one more means the body is too large.
+fn read_limited(reader: impl Read, limit: usize) -> Result<Vec<u8>, StatusCode> {
+    let mut out = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if out.len() > limit {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    Ok(out)
+}
+
+/// RFC 1950 header: deflate method and a CMF/FLG pair divisible by 31.
+fn has_zlib_header(raw: &[u8]) -> bool {
+    match raw {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+fn inflate(enc: Encoding, raw: &[u8], limit: usize) -> Result<Vec<u8>, StatusCode> {
+    use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+    match enc {
+        Encoding::Gzip => read_limited(GzDecoder::new(raw), limit),
+        // "deflate" is zlib-wrapped per RFC 9110, but plenty of clients send raw deflate.
+        Encoding::Deflate if has_zlib_header(raw) => read_limited(ZlibDecoder::new(raw), limit),
+        Encoding::Deflate => read_limited(DeflateDecoder::new(raw), limit),
+        Encoding::Zstd => {
+            let decoder =
+                zstd::stream::read::Decoder::new(raw).map_err(|_| StatusCode::BAD_REQUEST)?;
+            read_limited(decoder, limit)
+        }
+    }
+}
+
+/// Undo `Content-Encoding` on an ingest body. `limit` applies to the decoded size
+/// as well, so a small compressed body can't expand into an arbitrarily large one.
+pub fn decode_body(
+    metrics: &AppMetrics,
+    headers: &HeaderMap,
+    raw: Bytes,
+    limit: usize,
+) -> Result<Bytes, StatusCode> {
+    let Some(enc) = Encoding::from_headers(headers)? else {
+        return Ok(raw);
+    };
+    match inflate(enc, &raw, limit) {
+        Ok(decoded) => {
+            metrics.decompressed(enc.as_str(), raw.len(), decoded.len());
+            Ok(decoded.into())
+        }
+        Err(status) => {
+            let reason = if status == StatusCode::PAYLOAD_TOO_LARGE {
+                "decompressed_too_large"
+            } else {
+                "bad_encoding"
+            };
+            metrics.ingest_rejected_total(reason);
+            Err(status)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    fn encoded(enc: &'static str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert(header::CONTENT_ENCODING, HeaderValue::from_static(enc));
+        h
+    }
+
+    fn gzip(data: &[u8]) -> Bytes {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap().into()
+    }
+
+    #[test]
+    fn decodes_gzip_and_zstd() {
+        let m = AppMetrics::new();
+        let body = br#"{"metrics":{"temp_c":21.5}}"#;
+
+        let out = decode_body(&m, &encoded("gzip"), gzip(body), 1024).unwrap();
+        assert_eq!(&out[..], body);
+
+        let z = zstd::encode_all(&body[..], 3).unwrap();
+        let out = decode_body(&m, &encoded("zstd"), z.into(), 1024).unwrap();
+        assert_eq!(&out[..], body);
+    }
+
+    #[test]
+    fn decodes_zlib_and_raw_deflate() {
+        let m = AppMetrics::new();
+        let body = br#"{"metrics":{"temp_c":21.5}}"#;
+
+        let mut z = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        z.write_all(body).unwrap();
+        let out = decode_body(&m, &encoded("deflate"), z.finish().unwrap().into(), 1024);
+        assert_eq!(&out.unwrap()[..], body);
+
+        let mut d = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        d.write_all(body).unwrap();
+        let out = decode_body(&m, &encoded("deflate"), d.finish().unwrap().into(), 1024);
+        assert_eq!(&out.unwrap()[..], body);
+    }
+
+    #[test]
+    fn rejects_bombs_and_unknown_encodings() {
+        let m = AppMetrics::new();
+        let bomb = gzip(&vec![b' '; 1 << 20]);
+        assert!(bomb.len() < 4096);
+        assert_eq!(
+            decode_body(&m, &encoded("gzip"), bomb, 4096),
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+
+        let mut z = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        z.write_all(&vec![b' '; 1 << 20]).unwrap();
+        let bomb = Bytes::from(z.finish().unwrap());
+        assert_eq!(
+            decode_body(&m, &encoded("deflate"), bomb, 4096),
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        assert_eq!(
+            decode_body(&m, &encoded("br"), Bytes::from_static(b"{}"), 4096),
+            Err(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+        );
+    }
+}
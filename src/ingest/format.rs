@@ -0,0 +1,96 @@
+use axum::Json;
+use axum::http::{HeaderMap, StatusCode};
+use prost::Message;
+use serde::de::DeserializeOwned;
+
+use crate::ingest::handler::content_type;
+use crate::ingest::proto;
+use crate::ingest::types::IngestBody;
+
+/// Wire encodings accepted for a single ingest body, selected by `Content-Type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    Json,
+    Cbor,
+    MsgPack,
+    Protobuf,
+}
+
+impl BodyFormat {
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mime = content_type(headers)?.to_ascii_lowercase();
+        match mime.as_str() {
+            "application/json" => Some(BodyFormat::Json),
+            m if m.ends_with("+json") => Some(BodyFormat::Json),
+            "application/cbor" => Some(BodyFormat::Cbor),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(BodyFormat::MsgPack)
+            }
+            "application/x-protobuf"
+            | "application/protobuf"
+            | "application/vnd.google.protobuf" => Some(BodyFormat::Protobuf),
+            _ => None,
+        }
+    }
+
+    /// Decode a single ingest body in this format.
+    pub fn decode_ingest(self, raw: &[u8]) -> Result<IngestBody, StatusCode> {
+        match self {
+            BodyFormat::Protobuf => proto::Reading::decode(raw)
+                .map_err(|_| StatusCode::BAD_REQUEST)?
+                .into_batch_item()
+                .map(|item| item.body)
+                .map_err(|_| StatusCode::BAD_REQUEST),
+            _ => self.decode(raw),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, raw: &[u8]) -> Result<T, StatusCode> {
+        match self {
+            // Keep axum's JSON rejection statuses (400 syntax, 422 data).
+            BodyFormat::Json => Json::<T>::from_bytes(raw)
+                .map(|Json(v)| v)
+                .map_err(|rejection| rejection.status()),
+            BodyFormat::Cbor => ciborium::from_reader(raw).map_err(|_| StatusCode::BAD_REQUEST),
+            BodyFormat::MsgPack => rmp_serde::from_slice(raw).map_err(|_| StatusCode::BAD_REQUEST),
+            BodyFormat::Protobuf => Err(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> serde_json::Value {
+        json!({
+            "ts": 1_758_626_321,
+            "seq": 42,
+            "metrics": { "temp_c": 21.5 },
+            "tags": { "site": "AAL" },
+        })
+    }
+
+    #[test]
+    fn cbor_and_msgpack_decode_into_ingest_body() {
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&sample(), &mut cbor).unwrap();
+        let msgpack = rmp_serde::to_vec_named(&sample()).unwrap();
+
+        for (fmt, raw) in [(BodyFormat::Cbor, cbor), (BodyFormat::MsgPack, msgpack)] {
+            let body: IngestBody = fmt.decode(&raw).unwrap();
+            assert_eq!(body.seq, Some(42));
+            assert_eq!(body.metrics["temp_c"], 21.5);
+            assert_eq!(body.tags["site"], "AAL");
+            assert_eq!(body.ts.unwrap().unix_timestamp(), 1_758_626_321);
+        }
+    }
+
+    #[test]
+    fn json_ts_accepts_rfc3339() {
+        let raw = br#"{"ts":"2025-09-23T11:18:41Z"}"#;
+        let body: IngestBody = BodyFormat::Json.decode(raw).unwrap();
+        assert_eq!(body.ts.unwrap().unix_timestamp(), 1_758_626_321);
+    }
+}
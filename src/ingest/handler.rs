@@ -1,66 +1,82 @@
-use axum::Json;
+use axum::body::Bytes;
 use axum::extract::{Path, State};
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use time::OffsetDateTime;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
 
 use crate::app::AppState;
-use crate::config::AckMode;
-use crate::domain::Event;
-use crate::ingest::types::IngestBody;
+use crate::ingest::encoding::decode_body;
+use crate::ingest::format::BodyFormat;
+use crate::ingest::pipeline;
+use crate::ingest::types::{IngestAck, IngestBody};
 
-const MAX_METRICS: usize = 32;
-
-fn validate_maps(body: &IngestBody) -> Result<(), &'static str> {
-    if body.metrics.len() > MAX_METRICS {
-        return Err("too_many_metrics");
-    }
-    Ok(())
+/// Media type of the request, without parameters.
+pub(crate) fn content_type(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(str::trim)
 }
 
 #[utoipa::path(
     post,
     path = "/v1/ingest/{device_id}",
-    request_body = IngestBody,
+    request_body(
+        description = "Ingest body as JSON, CBOR or MessagePack (same shape), a protobuf `Reading` (proto/ingest.proto), or a packed binary blob for a device with a configured decoder",
+        content(
+            (IngestBody = "application/json"),
+            (IngestBody = "application/cbor"),
+            (IngestBody = "application/msgpack"),
+            ("application/x-protobuf"),
+            ("application/octet-stream")
+        )
+    ),
     params(
         ("device_id" = String, Path, description = "Device identifier")
     ),
     responses(
-        (status = 202, description = "Accepted (enqueued)"),
+        (status = 202, description = "Accepted (enqueued), or duplicate seq already accepted; new events also get an `X-Event-Id` header", body = IngestAck),
         (status = 400, description = "validation error"),
+        (status = 413, description = "Body exceeds max_payload_bytes, compressed or decompressed"),
+        (status = 415, description = "Unsupported Content-Type or Content-Encoding, or octet-stream without a decoder for the device"),
+        (status = 503, description = "Not ready, queue full or in-flight byte budget exhausted"),
     ),
     tag = "ingest"
 )]
 pub async fn ingest(
     State(st): State<AppState>,
     Path(device_id): Path<String>,
-    Json(body): Json<IngestBody>,
-) -> impl IntoResponse {
-    if !st.ready.is_ready(&st.cfg.health) {
-        return StatusCode::SERVICE_UNAVAILABLE;
-    }
-
-    if let Err(reason) = validate_maps(&body) {
-        st.metrics.ingest_rejected_total(reason);
-        return StatusCode::BAD_REQUEST;
+    headers: HeaderMap,
+    raw: Bytes,
+) -> Response {
+    let format = BodyFormat::from_headers(&headers);
+    let octet_stream = content_type(&headers)
+        .is_some_and(|ct| ct.eq_ignore_ascii_case("application/octet-stream"));
+    if format.is_none() && !octet_stream {
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
     }
-
-    let now = OffsetDateTime::now_utc();
-    let event = Event {
-        device_id,
-        ts: body.ts.unwrap_or(now),
-        seq: body.seq,
-        metrics: body.metrics,
-        tags: body.tags,
-        payload: body.payload,
-        received_at: now,
-        bytes: 0,
+    let raw = match decode_body(&st.metrics, &headers, raw, st.cfg.ingest.max_payload_bytes) {
+        Ok(raw) => raw,
+        Err(status) => return status.into_response(),
     };
-    match st.ingest_tx.try_send(event) {
-        Ok(_) => match st.cfg.ingest.ack_mode {
-            AckMode::Enqueue => StatusCode::ACCEPTED,
-            AckMode::Sink => StatusCode::OK,
+    let body = match format {
+        Some(format) => match format.decode_ingest(&raw) {
+            Ok(body) => body,
+            Err(status) => return status.into_response(),
+        },
+        // Raw binary blob: only meaningful with a decoder configured for the device.
+        None => match st.decoders.decode_raw(&device_id, &raw) {
+            Some(Ok(body)) => body,
+            Some(Err(reason)) => {
+                st.metrics.ingest_rejected_total(reason);
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+            None => return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
         },
-        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    match pipeline::submit(&st, &device_id, body, raw.len()) {
+        Ok(accepted) => pipeline::ack_response(&st, accepted),
+        Err(e) => e.status().into_response(),
     }
 }
@@ -0,0 +1,391 @@
+//! InfluxDB v2 `/api/v2/write` compatibility, so Telegraf's `outputs.influxdb_v2` can
+//! target the gateway unchanged. Each line-protocol point becomes one event: the
+//! measurement is kept as the `measurement` tag, numeric and boolean fields become
+//! metrics, string fields become tags like in SenML.
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+use utoipa::IntoParams;
+
+use crate::app::AppState;
+use crate::domain::MetricValue;
+use crate::ingest::encoding::decode_body;
+use crate::ingest::pipeline::{self, IngestError};
+use crate::ingest::types::{ErrorBody, IngestBody};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct WriteQuery {
+    /// Accepted for compatibility; ignored.
+    pub org: Option<String>,
+    /// Accepted for compatibility; ignored.
+    pub bucket: Option<String>,
+    /// Timestamp unit: `ns` (default), `us`, `ms` or `s`.
+    pub precision: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Precision {
+    Ns,
+    Us,
+    Ms,
+    S,
+}
+
+impl Precision {
+    fn parse(s: Option<&str>) -> Option<Self> {
+        match s {
+            None | Some("ns") => Some(Precision::Ns),
+            Some("us") => Some(Precision::Us),
+            Some("ms") => Some(Precision::Ms),
+            Some("s") => Some(Precision::S),
+            Some(_) => None,
+        }
+    }
+
+    fn to_time(self, ts: i64) -> Option<OffsetDateTime> {
+        let nanos_per_unit: i128 = match self {
+            Precision::Ns => 1,
+            Precision::Us => 1_000,
+            Precision::Ms => 1_000_000,
+            Precision::S => 1_000_000_000,
+        };
+        OffsetDateTime::from_unix_timestamp_nanos(i128::from(ts) * nanos_per_unit).ok()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum FieldValue {
+    Float(f64),
+    Int(i64),
+    UInt(u64),
+    Bool(bool),
+    Str(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct Point {
+    measurement: String,
+    tags: Vec<(String, String)>,
+    fields: Vec<(String, FieldValue)>,
+    ts: Option<i64>,
+}
+
+/// Splits on `sep` where it is neither backslash-escaped nor (optionally) inside
+/// a double-quoted string.
+fn split_unescaped(s: &str, sep: u8, quotes: bool) -> Vec<&str> {
+    let bytes = s.as_bytes();
+    let mut parts = Vec::new();
+    let (mut start, mut i, mut in_quotes) = (0, 0, false);
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' if quotes => in_quotes = !in_quotes,
+            b if b == sep && !in_quotes => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unescape(s: &str, specials: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && specials.contains(next) => {
+                out.push(*next);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn key_value(s: &str) -> Result<(&str, &str), String> {
+    match split_unescaped(s, b'=', false).as_slice() {
+        [k, _, ..] if !k.is_empty() => Ok((k, &s[k.len() + 1..])),
+        _ => Err(format!("expected key=value, got `{s}`")),
+    }
+}
+
+fn parse_field(raw: &str) -> Result<FieldValue, String> {
+    if let Some(inner) = raw.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| format!("unterminated string field `{raw}`"))?;
+        return Ok(FieldValue::Str(unescape(inner, &['"', '\\'])));
+    }
+    let bad = || format!("invalid field value `{raw}`");
+    if let Some(int) = raw.strip_suffix('i') {
+        return int.parse().map(FieldValue::Int).map_err(|_| bad());
+    }
+    if let Some(uint) = raw.strip_suffix('u') {
+        return uint.parse().map(FieldValue::UInt).map_err(|_| bad());
+    }
+    match raw {
+        "t" | "T" | "true" | "True" | "TRUE" => Ok(FieldValue::Bool(true)),
+        "f" | "F" | "false" | "False" | "FALSE" => Ok(FieldValue::Bool(false)),
+        _ => match raw.parse::<f64>() {
+            Ok(v) if v.is_finite() => Ok(FieldValue::Float(v)),
+            _ => Err(bad()),
+        },
+    }
+}
+
+fn parse_point(line: &str) -> Result<Point, String> {
+    // Quotes only delimit string field values; in the series section `"` is a
+    // literal character.
+    let line = line.trim_start_matches(' ');
+    let series = split_unescaped(line, b' ', false)[0];
+    let sections: Vec<&str> = split_unescaped(&line[series.len()..], b' ', true)
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect();
+    let (fields, ts) = match sections.as_slice() {
+        [fields] if !series.is_empty() => (*fields, None),
+        [fields, ts] if !series.is_empty() => (*fields, Some(*ts)),
+        _ => return Err("expected `measurement[,tags] fields [timestamp]`".into()),
+    };
+
+    let mut series = split_unescaped(series, b',', false).into_iter();
+    let measurement = unescape(series.next().unwrap_or_default(), &[',', ' ']);
+    if measurement.is_empty() {
+        return Err("missing measurement".into());
+    }
+    let tags = series
+        .map(|tag| {
+            let (k, v) = key_value(tag)?;
+            Ok((unescape(k, &[',', '=', ' ']), unescape(v, &[',', '=', ' '])))
+        })
+        .collect::<Result<_, String>>()?;
+    let fields = split_unescaped(fields, b',', true)
+        .into_iter()
+        .map(|field| {
+            let (k, v) = key_value(field)?;
+            Ok((unescape(k, &[',', '=', ' ']), parse_field(v)?))
+        })
+        .collect::<Result<_, String>>()?;
+    let ts = ts
+        .map(|ts| ts.parse().map_err(|_| format!("invalid timestamp `{ts}`")))
+        .transpose()?;
+
+    Ok(Point {
+        measurement,
+        tags,
+        fields,
+        ts,
+    })
+}
+
+/// Maps a point onto its device and ingest body.
+fn into_body(
+    point: Point,
+    precision: Precision,
+    device_tag: &str,
+) -> Result<(String, IngestBody), String> {
+    let ts = point
+        .ts
+        .map(|ts| precision.to_time(ts).ok_or("timestamp out of range"))
+        .transpose()?;
+    let mut device_id = None;
+    let mut tags = BTreeMap::from([("measurement".to_string(), point.measurement)]);
+    for (k, v) in point.tags {
+        if k == device_tag {
+            device_id = Some(v);
+        } else {
+            tags.insert(k, v);
+        }
+    }
+    let device_id = device_id.ok_or_else(|| format!("missing `{device_tag}` tag"))?;
+
+    let mut metrics = BTreeMap::new();
+    for (k, v) in point.fields {
+        let v = match v {
+            FieldValue::Float(v) => MetricValue::Float(v),
+            FieldValue::Int(v) => MetricValue::Int(v),
+            FieldValue::UInt(v) => MetricValue::UInt(v),
+            FieldValue::Bool(v) => MetricValue::Bool(v),
+            FieldValue::Str(v) => {
+                tags.insert(k, v);
+                continue;
+            }
+        };
+        metrics.insert(k, v);
+    }
+    Ok((
+        device_id,
+        IngestBody {
+            ts,
+            seq: None,
+            metrics,
+            tags,
+            units: BTreeMap::new(),
+            payload: serde_json::Value::Null,
+        },
+    ))
+}
+
+fn invalid(message: String, index: Option<usize>) -> Response {
+    let body = ErrorBody {
+        code: "invalid",
+        message,
+        index,
+    };
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/write",
+    request_body(
+        description = "InfluxDB line protocol; the device id is taken from the `ingest.influx_device_tag` tag (default `host`)",
+        content(("text/plain"))
+    ),
+    params(WriteQuery),
+    responses(
+        (status = 204, description = "All points accepted"),
+        (status = 400, description = "Some points could not be parsed or were rejected; the rest were written (partial write)", body = ErrorBody),
+        (status = 413, description = "Too many lines or body too large"),
+        (status = 415, description = "Unsupported Content-Encoding"),
+        (status = 503, description = "Not accepting, or some points hit backpressure; retrying may repeat earlier points"),
+    ),
+    tag = "ingest"
+)]
+pub async fn write(
+    State(st): State<AppState>,
+    Query(q): Query<WriteQuery>,
+    headers: HeaderMap,
+    raw: Bytes,
+) -> Response {
+    let Some(precision) = Precision::parse(q.precision.as_deref()) else {
+        return invalid("precision must be one of ns, us, ms, s".into(), None);
+    };
+    let raw = match decode_body(&st.metrics, &headers, raw, st.cfg.ingest.max_batch_bytes) {
+        Ok(raw) => raw,
+        Err(status) => return status.into_response(),
+    };
+    let Ok(text) = std::str::from_utf8(&raw) else {
+        return invalid("body is not UTF-8".into(), None);
+    };
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect();
+    if lines.len() > st.cfg.ingest.max_batch_items {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+    if !st.ready.is_ready(&st.cfg.health) {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let mut first_error = None;
+    let mut rejected = 0;
+    let mut backpressure = false;
+    for (index, line) in lines.iter().enumerate() {
+        let parsed = parse_point(line)
+            .and_then(|p| into_body(p, precision, &st.cfg.ingest.influx_device_tag));
+        let outcome = match parsed {
+            Ok((device_id, body)) => {
+                pipeline::submit(&st, &device_id, body, line.len()).map_err(|e| (e.to_string(), e))
+            }
+            Err(message) => {
+                st.metrics.ingest_rejected_total("invalid_line_protocol");
+                Err((message, IngestError::Invalid("invalid_line_protocol")))
+            }
+        };
+        if let Err((message, e)) = outcome {
+            rejected += 1;
+            backpressure |= !matches!(e, IngestError::Invalid(_));
+            first_error.get_or_insert((index, message));
+        }
+    }
+
+    match first_error {
+        None => StatusCode::NO_CONTENT.into_response(),
+        Some(_) if backpressure => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+        Some((index, message)) => invalid(
+            format!(
+                "partial write: {rejected} of {} points rejected; line {}: {message}",
+                lines.len(),
+                index + 1
+            ),
+            Some(index),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_escaped_line_protocol() {
+        let p = parse_point(
+            r#"my\ meas,host=dev\,1,site=a\=b temp=21.5,count=3i,ok=t,up=7u,msg="say \"hi\", ok" 1758628800000000000"#,
+        )
+        .unwrap();
+        assert_eq!(p.measurement, "my meas");
+        assert_eq!(
+            p.tags,
+            vec![
+                ("host".into(), "dev,1".into()),
+                ("site".into(), "a=b".into())
+            ]
+        );
+        assert_eq!(
+            p.fields,
+            vec![
+                ("temp".into(), FieldValue::Float(21.5)),
+                ("count".into(), FieldValue::Int(3)),
+                ("ok".into(), FieldValue::Bool(true)),
+                ("up".into(), FieldValue::UInt(7)),
+                ("msg".into(), FieldValue::Str(r#"say "hi", ok"#.into())),
+            ]
+        );
+        assert_eq!(p.ts, Some(1_758_628_800_000_000_000));
+
+        // `"` is only a quote inside field values.
+        let p = parse_point(r#"say"s,host=dev"1,note=a"b\ c t=1"#).unwrap();
+        assert_eq!(p.measurement, r#"say"s"#);
+        assert_eq!(
+            p.tags,
+            vec![
+                ("host".into(), r#"dev"1"#.into()),
+                ("note".into(), r#"a"b c"#.into())
+            ]
+        );
+        assert_eq!(p.fields, vec![("t".into(), FieldValue::Float(1.0))]);
+
+        assert!(parse_point("cpu").is_err());
+        assert!(parse_point("cpu usage=abc").is_err());
+        assert!(parse_point("cpu,host usage=1").is_err());
+    }
+
+    #[test]
+    fn maps_point_to_device_and_body() {
+        let p = parse_point("cpu,host=dev1,cpu=cpu0 usage=12.5,state=\"idle\" 1758628800").unwrap();
+        let (device_id, body) = into_body(p, Precision::S, "host").unwrap();
+        assert_eq!(device_id, "dev1");
+        assert_eq!(body.metrics["usage"], 12.5);
+        assert_eq!(body.tags["measurement"], "cpu");
+        assert_eq!(body.tags["cpu"], "cpu0");
+        assert_eq!(body.tags["state"], "idle");
+        assert_eq!(body.ts.unwrap().unix_timestamp(), 1_758_628_800);
+
+        let p = parse_point("cpu usage=1").unwrap();
+        assert!(into_body(p, Precision::Ns, "host").is_err());
+    }
+}
@@ -0,0 +1,403 @@
+//! Uplink webhooks from LoRaWAN network servers: The Things Stack (v3) and
+//! ChirpStack (v4 HTTP integration). The DevEUI is the device id, the frame
+//! counter is `seq`, decoded payload fields become metrics and radio metadata
+//! from the strongest gateway becomes tags.
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use crate::app::AppState;
+use crate::domain::MetricValue;
+use crate::ingest::format::BodyFormat;
+use crate::ingest::pipeline;
+use crate::ingest::types::{IngestAck, IngestBody};
+
+/// Network-server independent view of one uplink.
+#[derive(Debug, Default, PartialEq)]
+struct Uplink {
+    dev_eui: String,
+    f_cnt: Option<u64>,
+    f_port: Option<u64>,
+    received_at: Option<OffsetDateTime>,
+    /// Base64 `FRMPayload`, kept in the event payload for later decoding.
+    frm_payload: Option<String>,
+    decoded: Option<Value>,
+    application: Option<String>,
+    gateway_id: Option<String>,
+    rssi: Option<f64>,
+    snr: Option<f64>,
+    gateways: usize,
+    frequency: Option<u64>,
+    spreading_factor: Option<u64>,
+}
+
+mod tts {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    pub struct UplinkEvent {
+        pub end_device_ids: EndDeviceIds,
+        pub received_at: Option<String>,
+        pub uplink_message: UplinkMessage,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct EndDeviceIds {
+        pub dev_eui: Option<String>,
+        pub application_ids: Option<ApplicationIds>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ApplicationIds {
+        pub application_id: String,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    pub struct UplinkMessage {
+        pub f_port: Option<u64>,
+        pub f_cnt: Option<u64>,
+        pub frm_payload: Option<String>,
+        pub decoded_payload: Option<Value>,
+        pub rx_metadata: Vec<RxMetadata>,
+        pub settings: Option<TxSettings>,
+        pub received_at: Option<String>,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    pub struct RxMetadata {
+        pub gateway_ids: Option<GatewayIds>,
+        pub rssi: Option<f64>,
+        pub snr: Option<f64>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct GatewayIds {
+        pub gateway_id: String,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    pub struct TxSettings {
+        pub data_rate: Option<Value>,
+        /// Hz, as a decimal string.
+        pub frequency: Option<String>,
+    }
+
+    impl From<UplinkEvent> for Uplink {
+        fn from(ev: UplinkEvent) -> Self {
+            let msg = ev.uplink_message;
+            let best = msg.rx_metadata.iter().max_by(|a, b| {
+                a.rssi
+                    .unwrap_or(f64::MIN)
+                    .total_cmp(&b.rssi.unwrap_or(f64::MIN))
+            });
+            let settings = msg.settings.unwrap_or_default();
+            Uplink {
+                dev_eui: ev.end_device_ids.dev_eui.unwrap_or_default(),
+                f_cnt: msg.f_cnt,
+                f_port: msg.f_port,
+                received_at: parse_time(msg.received_at.or(ev.received_at)),
+                frm_payload: msg.frm_payload,
+                decoded: msg.decoded_payload,
+                application: ev.end_device_ids.application_ids.map(|a| a.application_id),
+                gateway_id: best
+                    .and_then(|m| m.gateway_ids.as_ref())
+                    .map(|g| g.gateway_id.clone()),
+                rssi: best.and_then(|m| m.rssi),
+                snr: best.and_then(|m| m.snr),
+                gateways: msg.rx_metadata.len(),
+                frequency: settings.frequency.and_then(|f| f.parse().ok()),
+                spreading_factor: settings
+                    .data_rate
+                    .as_ref()
+                    .and_then(|dr| dr.pointer("/lora/spreading_factor"))
+                    .and_then(Value::as_u64),
+            }
+        }
+    }
+}
+
+mod chirpstack {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct UplinkEvent {
+        pub time: Option<String>,
+        pub device_info: DeviceInfo,
+        pub f_cnt: Option<u64>,
+        pub f_port: Option<u64>,
+        pub data: Option<String>,
+        pub object: Option<Value>,
+        #[serde(default)]
+        pub rx_info: Vec<RxInfo>,
+        pub tx_info: Option<TxInfo>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct DeviceInfo {
+        pub dev_eui: String,
+        pub application_name: Option<String>,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(rename_all = "camelCase", default)]
+    pub struct RxInfo {
+        pub gateway_id: Option<String>,
+        pub rssi: Option<f64>,
+        pub snr: Option<f64>,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(rename_all = "camelCase", default)]
+    pub struct TxInfo {
+        pub frequency: Option<u64>,
+        pub modulation: Option<Value>,
+    }
+
+    impl From<UplinkEvent> for Uplink {
+        fn from(ev: UplinkEvent) -> Self {
+            let best = ev.rx_info.iter().max_by(|a, b| {
+                a.rssi
+                    .unwrap_or(f64::MIN)
+                    .total_cmp(&b.rssi.unwrap_or(f64::MIN))
+            });
+            let tx = ev.tx_info.unwrap_or_default();
+            Uplink {
+                dev_eui: ev.device_info.dev_eui,
+                f_cnt: ev.f_cnt,
+                f_port: ev.f_port,
+                received_at: parse_time(ev.time),
+                frm_payload: ev.data,
+                decoded: ev.object,
+                application: ev.device_info.application_name,
+                gateway_id: best.and_then(|r| r.gateway_id.clone()),
+                rssi: best.and_then(|r| r.rssi),
+                snr: best.and_then(|r| r.snr),
+                gateways: ev.rx_info.len(),
+                frequency: tx.frequency,
+                spreading_factor: tx
+                    .modulation
+                    .as_ref()
+                    .and_then(|m| m.pointer("/lora/spreadingFactor"))
+                    .and_then(Value::as_u64),
+            }
+        }
+    }
+}
+
+fn parse_time(s: Option<String>) -> Option<OffsetDateTime> {
+    s.and_then(|s| OffsetDateTime::parse(&s, &Rfc3339).ok())
+}
+
+/// Flattens decoded payload fields: numbers, booleans and numeric arrays become
+/// metrics, strings become tags, nested objects are joined with `_`
+/// (`{"gps":{"lat":1}}` → `gps_lat`).
+fn flatten(prefix: &str, v: &Value, body: &mut IngestBody) {
+    match v {
+        Value::Number(_) | Value::Bool(_) | Value::Array(_) => {
+            if let Ok(m) = MetricValue::deserialize(v) {
+                body.metrics.insert(prefix.to_string(), m);
+            }
+        }
+        Value::String(s) => {
+            body.tags.insert(prefix.to_string(), s.clone());
+        }
+        Value::Object(map) => {
+            for (k, v) in map {
+                let key = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{prefix}_{k}")
+                };
+                flatten(&key, v, body);
+            }
+        }
+        Value::Null => {}
+    }
+}
+
+impl Uplink {
+    fn into_body(self) -> Result<(String, IngestBody), &'static str> {
+        let device_id = self.dev_eui.to_ascii_lowercase();
+        if device_id.len() != 16 || !device_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err("invalid_dev_eui");
+        }
+        let mut body = IngestBody {
+            ts: self.received_at,
+            seq: self.f_cnt,
+            metrics: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            units: BTreeMap::new(),
+            payload: json!({ "f_port": self.f_port, "frm_payload": self.frm_payload }),
+        };
+        if let Some(decoded) = &self.decoded {
+            flatten("", decoded, &mut body);
+        }
+
+        let tags = [
+            ("application", self.application),
+            ("gateway_id", self.gateway_id),
+            ("rssi", self.rssi.map(|v| v.to_string())),
+            ("snr", self.snr.map(|v| v.to_string())),
+            ("gateways", Some(self.gateways.to_string())),
+            ("frequency", self.frequency.map(|v| v.to_string())),
+            (
+                "spreading_factor",
+                self.spreading_factor.map(|v| v.to_string()),
+            ),
+            ("f_port", self.f_port.map(|v| v.to_string())),
+        ];
+        for (k, v) in tags {
+            if let Some(v) = v {
+                body.tags.insert(k.to_string(), v);
+            }
+        }
+        Ok((device_id, body))
+    }
+}
+
+fn submit(st: &AppState, uplink: Uplink, bytes: usize) -> Response {
+    let (device_id, body) = match uplink.into_body() {
+        Ok(parsed) => parsed,
+        Err(reason) => {
+            st.metrics.ingest_rejected_total(reason);
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+    match pipeline::submit(st, &device_id, body, bytes) {
+        Ok(accepted) => pipeline::ack_response(st, accepted),
+        Err(e) => e.status().into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/lorawan/tts/uplink",
+    request_body(description = "The Things Stack v3 uplink message webhook", content(("application/json"))),
+    responses(
+        (status = 202, description = "Accepted (enqueued), or duplicate frame counter; new events also get an `X-Event-Id` header", body = IngestAck),
+        (status = 400, description = "Not a TTS uplink, or no valid DevEUI"),
+        (status = 503, description = "Not ready, queue full or in-flight byte budget exhausted"),
+    ),
+    tag = "ingest"
+)]
+pub async fn tts_uplink(State(st): State<AppState>, raw: Bytes) -> Response {
+    match BodyFormat::Json.decode::<tts::UplinkEvent>(&raw) {
+        Ok(ev) => submit(&st, ev.into(), raw.len()),
+        Err(status) => status.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChirpstackQuery {
+    pub event: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/lorawan/chirpstack",
+    request_body(description = "ChirpStack v4 HTTP integration event (JSON marshaler); only `event=up` is ingested", content(("application/json"))),
+    params(
+        ("event" = Option<String>, Query, description = "ChirpStack event type; anything but `up` is acknowledged and ignored")
+    ),
+    responses(
+        (status = 202, description = "Accepted (enqueued), or duplicate frame counter; new events also get an `X-Event-Id` header", body = IngestAck),
+        (status = 204, description = "Not an uplink event; ignored"),
+        (status = 400, description = "Not a ChirpStack uplink, or no valid DevEUI"),
+        (status = 503, description = "Not ready, queue full or in-flight byte budget exhausted"),
+    ),
+    tag = "ingest"
+)]
+pub async fn chirpstack_event(
+    State(st): State<AppState>,
+    Query(q): Query<ChirpstackQuery>,
+    raw: Bytes,
+) -> Response {
+    if q.event.as_deref().is_some_and(|e| e != "up") {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    match BodyFormat::Json.decode::<chirpstack::UplinkEvent>(&raw) {
+        Ok(ev) => submit(&st, ev.into(), raw.len()),
+        Err(status) => status.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_tts_uplink() {
+        let ev: tts::UplinkEvent = serde_json::from_str(
+            r#"{
+              "end_device_ids": {"device_id": "eui-70b3", "dev_eui": "70B3D57ED0000001",
+                                 "application_ids": {"application_id": "farm"}},
+              "received_at": "2025-09-23T12:00:00.5Z",
+              "uplink_message": {
+                "f_port": 2, "f_cnt": 42, "frm_payload": "AQI=",
+                "decoded_payload": {"temperature": 21.5, "valve_open": true, "gps": {"lat": 52.1}, "mode": "eco"},
+                "rx_metadata": [
+                  {"gateway_ids": {"gateway_id": "gw-far"}, "rssi": -110, "snr": -3.5},
+                  {"gateway_ids": {"gateway_id": "gw-near"}, "rssi": -60, "snr": 9.5}
+                ],
+                "settings": {"data_rate": {"lora": {"bandwidth": 125000, "spreading_factor": 7}},
+                             "frequency": "868100000"}
+              }
+            }"#,
+        )
+        .unwrap();
+        let (device_id, body) = Uplink::from(ev).into_body().unwrap();
+        assert_eq!(device_id, "70b3d57ed0000001");
+        assert_eq!(body.seq, Some(42));
+        assert_eq!(body.metrics["temperature"], 21.5);
+        assert_eq!(body.metrics["valve_open"], MetricValue::Bool(true));
+        assert_eq!(body.metrics["gps_lat"], 52.1);
+        assert_eq!(body.tags["mode"], "eco");
+        assert_eq!(body.tags["gateway_id"], "gw-near");
+        assert_eq!(body.tags["rssi"], "-60");
+        assert_eq!(body.tags["snr"], "9.5");
+        assert_eq!(body.tags["gateways"], "2");
+        assert_eq!(body.tags["spreading_factor"], "7");
+        assert_eq!(body.tags["frequency"], "868100000");
+        assert_eq!(body.payload["frm_payload"], "AQI=");
+        assert!(body.ts.is_some());
+    }
+
+    #[test]
+    fn maps_chirpstack_uplink() {
+        let ev: chirpstack::UplinkEvent = serde_json::from_str(
+            r#"{
+              "time": "2025-09-23T12:00:00Z",
+              "deviceInfo": {"devEui": "0101010101010101", "applicationName": "farm"},
+              "fCnt": 10, "fPort": 1, "data": "AQI=",
+              "object": {"humidity": 40},
+              "rxInfo": [{"gatewayId": "0016c001f153a14c", "rssi": -57, "snr": 10}],
+              "txInfo": {"frequency": 868300000, "modulation": {"lora": {"spreadingFactor": 9}}}
+            }"#,
+        )
+        .unwrap();
+        let (device_id, body) = Uplink::from(ev).into_body().unwrap();
+        assert_eq!(device_id, "0101010101010101");
+        assert_eq!(body.seq, Some(10));
+        assert_eq!(body.metrics["humidity"], 40.0);
+        assert_eq!(body.tags["gateway_id"], "0016c001f153a14c");
+        assert_eq!(body.tags["spreading_factor"], "9");
+
+        let bad = Uplink {
+            dev_eui: "nope".into(),
+            ..Default::default()
+        };
+        assert_eq!(bad.into_body().unwrap_err(), "invalid_dev_eui");
+    }
+}
@@ -1,2 +1,15 @@
+pub mod batch;
+pub mod budget;
+pub mod decoder;
+pub mod encoding;
+pub mod format;
 pub mod handler;
+pub mod influx;
+pub mod lorawan;
+pub mod otlp;
+pub mod pipeline;
+pub mod prometheus;
+pub mod proto;
+pub mod senml;
 pub mod types;
+pub mod ws;
@@ -0,0 +1,513 @@
+//! OTLP/HTTP metrics (`POST /v1/metrics`), protobuf or JSON. Gauge and sum data
+//! points are mapped onto events; other metric types are ignored.
+//!
+//! The messages are a hand-written subset of `opentelemetry/proto/metrics/v1`. Oneof
+//! members are declared as optional fields (the same on the wire), which lets one
+//! set of types carry both the prost and the OTLP JSON mapping.
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+
+use crate::app::AppState;
+use crate::domain::MetricValue;
+use crate::ingest::encoding::decode_body;
+use crate::ingest::handler::content_type;
+use crate::ingest::pipeline::{self, IngestError, MAX_METRICS};
+use crate::ingest::types::IngestBody;
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportMetricsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ResourceMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    pub attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScopeMetrics {
+    #[prost(message, repeated, tag = "2")]
+    pub metrics: Vec<Metric>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Metric {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub unit: String,
+    /// `data` oneof: gauge.
+    #[prost(message, optional, tag = "5")]
+    pub gauge: Option<Gauge>,
+    /// `data` oneof: sum.
+    #[prost(message, optional, tag = "7")]
+    pub sum: Option<Sum>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Gauge {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Sum {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NumberDataPoint {
+    #[prost(fixed64, tag = "3")]
+    #[serde(with = "json_int")]
+    pub time_unix_nano: u64,
+    /// `value` oneof: as_double.
+    #[prost(double, optional, tag = "4")]
+    pub as_double: Option<f64>,
+    /// `value` oneof: as_int.
+    #[prost(sfixed64, optional, tag = "6")]
+    #[serde(with = "json_int::option")]
+    pub as_int: Option<i64>,
+    #[prost(message, repeated, tag = "7")]
+    pub attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<AnyValue>,
+}
+
+/// Scalar members of the `AnyValue` oneof; arrays, kvlists and bytes are dropped.
+#[derive(Clone, PartialEq, prost::Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnyValue {
+    #[prost(string, optional, tag = "1")]
+    pub string_value: Option<String>,
+    #[prost(bool, optional, tag = "2")]
+    pub bool_value: Option<bool>,
+    #[prost(int64, optional, tag = "3")]
+    #[serde(with = "json_int::option")]
+    pub int_value: Option<i64>,
+    #[prost(double, optional, tag = "4")]
+    pub double_value: Option<f64>,
+}
+
+impl AnyValue {
+    fn as_tag(&self) -> Option<String> {
+        self.string_value
+            .clone()
+            .or_else(|| self.bool_value.map(|v| v.to_string()))
+            .or_else(|| self.int_value.map(|v| v.to_string()))
+            .or_else(|| self.double_value.map(|v| v.to_string()))
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportMetricsServiceResponse {
+    #[prost(message, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partial_success: Option<ExportMetricsPartialSuccess>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportMetricsPartialSuccess {
+    #[prost(int64, tag = "1")]
+    pub rejected_data_points: i64,
+    #[prost(string, tag = "2")]
+    pub error_message: String,
+}
+
+/// OTLP JSON allows 64-bit integers as numbers or decimal strings.
+mod json_int {
+    use serde::{Deserialize, Deserializer, de::Error};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumOrString {
+        Unsigned(u64),
+        Signed(i64),
+        Str(String),
+    }
+
+    fn parse<T: TryFrom<i128>, E: Error>(v: NumOrString) -> Result<T, E> {
+        let n = match v {
+            NumOrString::Unsigned(n) => i128::from(n),
+            NumOrString::Signed(n) => i128::from(n),
+            NumOrString::Str(s) => s.parse().map_err(E::custom)?,
+        };
+        T::try_from(n).map_err(|_| E::custom("integer out of range"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<i128>>(d: D) -> Result<T, D::Error> {
+        parse(NumOrString::deserialize(d)?)
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<i128>>(
+            d: D,
+        ) -> Result<Option<T>, D::Error> {
+            Option::<NumOrString>::deserialize(d)?
+                .map(parse)
+                .transpose()
+        }
+    }
+}
+
+/// Seconds an exporter is asked to wait after backpressure.
+const RETRY_AFTER_S: &str = "1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OtlpFormat {
+    Protobuf,
+    Json,
+}
+
+impl OtlpFormat {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        match content_type(headers)?.to_ascii_lowercase().as_str() {
+            "application/x-protobuf" | "application/protobuf" => Some(OtlpFormat::Protobuf),
+            "application/json" => Some(OtlpFormat::Json),
+            _ => None,
+        }
+    }
+
+    fn decode(self, raw: &[u8]) -> Option<ExportMetricsServiceRequest> {
+        match self {
+            OtlpFormat::Protobuf => ExportMetricsServiceRequest::decode(raw).ok(),
+            OtlpFormat::Json => serde_json::from_slice(raw).ok(),
+        }
+    }
+
+    fn respond(self, status: StatusCode, resp: ExportMetricsServiceResponse) -> Response {
+        match self {
+            OtlpFormat::Protobuf => (
+                status,
+                [(header::CONTENT_TYPE, "application/x-protobuf")],
+                resp.encode_to_vec(),
+            )
+                .into_response(),
+            OtlpFormat::Json => (status, Json(resp)).into_response(),
+        }
+    }
+}
+
+/// Events built from a request, plus the number of data points that had no device.
+struct Mapped {
+    bodies: Vec<(String, IngestBody, usize)>,
+    unattributed: usize,
+}
+
+/// Folds data points sharing device, time and attributes into bodies keyed by
+/// metric name, at most `MAX_METRICS` metrics per body. The first of `device_keys` found on the data point or
+/// its resource is the device id; the remaining attributes become tags.
+fn map_request(req: ExportMetricsServiceRequest, device_keys: &[String]) -> Mapped {
+    type Key = (String, u64, BTreeMap<String, String>);
+    let mut groups: BTreeMap<Key, (IngestBody, usize)> = BTreeMap::new();
+    let mut unattributed = 0;
+
+    for rm in req.resource_metrics {
+        let resource: BTreeMap<String, String> = attributes(rm.resource.map(|r| r.attributes));
+        for metric in rm.scope_metrics.into_iter().flat_map(|s| s.metrics) {
+            let points = match (metric.gauge, metric.sum) {
+                (Some(g), _) => g.data_points,
+                (None, Some(s)) => s.data_points,
+                (None, None) => continue,
+            };
+            for point in points {
+                let value: MetricValue = match (point.as_double, point.as_int) {
+                    (Some(v), _) => v.into(),
+                    (None, Some(v)) => v.into(),
+                    (None, None) => continue,
+                };
+                let mut tags = resource.clone();
+                tags.extend(attributes(Some(point.attributes)));
+                let Some(device_id) = device_keys
+                    .iter()
+                    .find_map(|k| tags.remove(k).filter(|v| !v.is_empty()))
+                else {
+                    unattributed += 1;
+                    continue;
+                };
+                for k in device_keys {
+                    tags.remove(k);
+                }
+                let key = (device_id, point.time_unix_nano, tags);
+                let (body, points) = groups.entry(key.clone()).or_insert_with(|| {
+                    let ts = (key.1 > 0)
+                        .then(|| OffsetDateTime::from_unix_timestamp_nanos(i128::from(key.1)).ok())
+                        .flatten();
+                    (
+                        IngestBody {
+                            ts,
+                            seq: None,
+                            metrics: BTreeMap::new(),
+                            tags: key.2.clone(),
+                            units: BTreeMap::new(),
+                            payload: serde_json::Value::Null,
+                        },
+                        0,
+                    )
+                });
+                body.metrics.insert(metric.name.clone(), value);
+                if !metric.unit.is_empty() {
+                    body.units.insert(metric.name.clone(), metric.unit.clone());
+                }
+                *points += 1;
+            }
+        }
+    }
+
+    let mut bodies = Vec::new();
+    for ((device_id, _, _), (body, points)) in groups {
+        let IngestBody {
+            ts,
+            metrics,
+            tags,
+            units,
+            ..
+        } = body;
+        // Repeated points for one metric overwrite each other; they are
+        // counted with the first chunk.
+        let mut extra = points - metrics.len();
+        let metrics: Vec<_> = metrics.into_iter().collect();
+        for chunk in metrics.chunks(MAX_METRICS) {
+            let units = chunk
+                .iter()
+                .filter_map(|(name, _)| Some((name.clone(), units.get(name)?.clone())))
+                .collect();
+            bodies.push((
+                device_id.clone(),
+                IngestBody {
+                    ts,
+                    seq: None,
+                    metrics: chunk.iter().cloned().collect(),
+                    tags: tags.clone(),
+                    units,
+                    payload: serde_json::Value::Null,
+                },
+                chunk.len() + std::mem::take(&mut extra),
+            ));
+        }
+    }
+
+    Mapped {
+        bodies,
+        unattributed,
+    }
+}
+
+fn attributes(kvs: Option<Vec<KeyValue>>) -> BTreeMap<String, String> {
+    kvs.unwrap_or_default()
+        .into_iter()
+        .filter_map(|kv| Some((kv.key, kv.value?.as_tag()?)))
+        .collect()
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/metrics",
+    request_body(
+        description = "OTLP `ExportMetricsServiceRequest`; gauge and sum data points become events",
+        content(("application/x-protobuf"), ("application/json"))
+    ),
+    responses(
+        (status = 200, description = "`ExportMetricsServiceResponse`, with `partial_success` when data points were rejected"),
+        (status = 400, description = "Malformed request"),
+        (status = 413, description = "Too many events or body too large"),
+        (status = 415, description = "Unsupported Content-Type or Content-Encoding"),
+        (status = 503, description = "Not accepting, or backpressure before any data point was queued; see `Retry-After`"),
+    ),
+    tag = "ingest"
+)]
+pub async fn export_metrics(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    raw: Bytes,
+) -> Response {
+    let Some(format) = OtlpFormat::from_headers(&headers) else {
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+    };
+    let raw = match decode_body(&st.metrics, &headers, raw, st.cfg.ingest.max_batch_bytes) {
+        Ok(raw) => raw,
+        Err(status) => return status.into_response(),
+    };
+    let Some(req) = format.decode(&raw) else {
+        st.metrics.ingest_rejected_total("invalid_otlp");
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let mapped = map_request(req, &st.cfg.ingest.otlp_device_attributes);
+    if mapped.bodies.len() > st.cfg.ingest.max_batch_items {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+    if !st.ready.is_ready(&st.cfg.health) {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let mut rejected = mapped.unattributed;
+    let mut first_error = (mapped.unattributed > 0).then(|| "missing_device_id".to_string());
+    if mapped.unattributed > 0 {
+        st.metrics.ingest_rejected_total("missing_device_id");
+    }
+    let bytes = raw.len() / mapped.bodies.len().max(1);
+    let mut enqueued = 0;
+    let mut bodies = mapped.bodies.into_iter();
+    while let Some((device_id, body, points)) = bodies.next() {
+        match pipeline::submit(&st, &device_id, body, bytes) {
+            Ok(_) => enqueued += 1,
+            Err(IngestError::Invalid(reason)) => {
+                rejected += points;
+                first_error.get_or_insert_with(|| reason.to_string());
+            }
+            // Exporters never retry a partial success, so only answer with one
+            // when part of the export is already queued.
+            Err(_) if enqueued == 0 => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(header::RETRY_AFTER, RETRY_AFTER_S)],
+                )
+                    .into_response();
+            }
+            Err(e) => {
+                rejected += points + bodies.map(|(_, _, points)| points).sum::<usize>();
+                first_error.get_or_insert_with(|| e.code().to_string());
+                break;
+            }
+        }
+    }
+
+    let partial_success = first_error.map(|error_message| ExportMetricsPartialSuccess {
+        rejected_data_points: rejected as i64,
+        error_message,
+    });
+    format.respond(
+        StatusCode::OK,
+        ExportMetricsServiceResponse { partial_success },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON: &str = r#"{"resourceMetrics":[{
+        "resource":{"attributes":[{"key":"device.id","value":{"stringValue":"dev1"}},
+                                  {"key":"site","value":{"stringValue":"lab"}}]},
+        "scopeMetrics":[{"metrics":[
+            {"name":"temp_c","unit":"Cel","gauge":{"dataPoints":[
+                {"timeUnixNano":"1758628800000000000","asDouble":21.5}]}},
+            {"name":"restarts","sum":{"dataPoints":[
+                {"timeUnixNano":"1758628800000000000","asInt":"3"},
+                {"timeUnixNano":"1758628800000000000","asInt":1,
+                 "attributes":[{"key":"core","value":{"intValue":"1"}}]}],
+                "aggregationTemporality":2,"isMonotonic":true}},
+            {"name":"latency","histogram":{"dataPoints":[{"count":"2"}]}}
+        ]}]}]}"#;
+
+    fn device_keys() -> Vec<String> {
+        vec!["device.id".into(), "host.name".into()]
+    }
+
+    #[test]
+    fn maps_json_gauge_and_sum_points() {
+        let req: ExportMetricsServiceRequest = serde_json::from_str(JSON).unwrap();
+        let mapped = map_request(req, &device_keys());
+        assert_eq!(mapped.unattributed, 0);
+        assert_eq!(mapped.bodies.len(), 2);
+
+        let (with_core, without_core): (Vec<_>, Vec<_>) = mapped
+            .bodies
+            .iter()
+            .partition(|(_, body, _)| body.tags.contains_key("core"));
+
+        let (device_id, body, points) = without_core[0];
+        assert_eq!(device_id, "dev1");
+        assert_eq!(*points, 2);
+        assert_eq!(body.metrics["temp_c"], 21.5);
+        assert_eq!(body.metrics["restarts"], 3.0);
+        assert_eq!(body.tags["site"], "lab");
+        assert_eq!(body.units["temp_c"], "Cel");
+        assert!(!body.tags.contains_key("device.id"));
+        assert_eq!(body.ts.unwrap().unix_timestamp(), 1_758_628_800);
+
+        let (_, body, _) = with_core[0];
+        assert_eq!(body.tags["core"], "1");
+        assert_eq!(body.metrics["restarts"], 1.0);
+    }
+
+    #[test]
+    fn splits_wide_groups_at_max_metrics() {
+        let metrics: Vec<String> = (0..MAX_METRICS + 1)
+            .map(|i| {
+                format!(
+                    r#"{{"name":"m{i}","unit":"1","gauge":{{"dataPoints":[
+                        {{"timeUnixNano":"1758628800000000000","asInt":{i}}}]}}}}"#
+                )
+            })
+            .collect();
+        let json = format!(
+            r#"{{"resourceMetrics":[{{
+                "resource":{{"attributes":[{{"key":"device.id","value":{{"stringValue":"dev1"}}}}]}},
+                "scopeMetrics":[{{"metrics":[{}]}}]}}]}}"#,
+            metrics.join(",")
+        );
+        let req: ExportMetricsServiceRequest = serde_json::from_str(&json).unwrap();
+        let mapped = map_request(req, &device_keys());
+
+        assert_eq!(mapped.bodies.len(), 2);
+        let (_, first, points) = &mapped.bodies[0];
+        assert_eq!(first.metrics.len(), MAX_METRICS);
+        assert_eq!(*points, MAX_METRICS);
+        assert_eq!(first.units.len(), MAX_METRICS);
+        let (device_id, second, points) = &mapped.bodies[1];
+        assert_eq!(device_id, "dev1");
+        assert_eq!(second.metrics.len(), 1);
+        assert_eq!(*points, 1);
+        assert_eq!(second.units.len(), 1);
+        assert_eq!(second.ts, first.ts);
+    }
+
+    #[test]
+    fn protobuf_round_trips_and_counts_points_without_device() {
+        let req: ExportMetricsServiceRequest = serde_json::from_str(JSON).unwrap();
+        let decoded = ExportMetricsServiceRequest::decode(req.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, req);
+
+        let mapped = map_request(decoded, &["service.instance.id".to_string()]);
+        assert!(mapped.bodies.is_empty());
+        assert_eq!(mapped.unattributed, 3);
+    }
+}
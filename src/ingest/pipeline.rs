@@ -0,0 +1,196 @@
+use axum::Json;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use time::OffsetDateTime;
+use ulid::Ulid;
+
+use crate::app::AppState;
+use crate::config::AckMode;
+use crate::domain::{Event, MetricValue};
+use crate::ingest::types::{IngestAck, IngestBody, ItemStatus};
+use crate::sequence::SeqOutcome;
+
+pub(crate) const MAX_METRICS: usize = 32;
+/// Per-value caps for array (spectrum) and string (state) metrics.
+pub(crate) const MAX_ARRAY_LEN: usize = 4096;
+pub(crate) const MAX_STRING_LEN: usize = 256;
+
+/// Result of pushing one body into the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accepted {
+    /// Carries the id assigned to the new event.
+    Enqueued(Ulid),
+    /// Same `(device_id, seq)` already accepted; not enqueued again.
+    Duplicate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum IngestError {
+    #[error("not accepting")]
+    NotReady,
+    #[error("queue full")]
+    QueueFull,
+    #[error("in-flight byte budget exhausted")]
+    InflightBytes,
+    #[error("invalid: {0}")]
+    Invalid(&'static str),
+}
+
+impl IngestError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            IngestError::NotReady => "not_ready",
+            IngestError::QueueFull => "queue_full",
+            IngestError::InflightBytes => "inflight_bytes",
+            IngestError::Invalid(reason) => reason,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            IngestError::Invalid(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// Header carrying the event id on single-event ingest responses.
+pub const EVENT_ID_HEADER: &str = "x-event-id";
+
+/// Status code a successful ingest is acknowledged with.
+pub fn ack_status(st: &AppState) -> StatusCode {
+    match st.cfg.ingest.ack_mode {
+        AckMode::Enqueue => StatusCode::ACCEPTED,
+        AckMode::Sink => StatusCode::OK,
+    }
+}
+
+/// Response to a single-event ingest: the ack status, an `X-Event-Id` header for
+/// new events, and an `IngestAck` body.
+pub fn ack_response(st: &AppState, accepted: Accepted) -> Response {
+    let ack = match accepted {
+        Accepted::Enqueued(id) => IngestAck {
+            status: ItemStatus::Accepted,
+            event_id: Some(id),
+        },
+        Accepted::Duplicate => IngestAck {
+            status: ItemStatus::Duplicate,
+            event_id: None,
+        },
+    };
+    let mut resp = (ack_status(st), Json(&ack)).into_response();
+    if let Some(id) = ack.event_id
+        && let Ok(v) = HeaderValue::from_str(&id.to_string())
+    {
+        resp.headers_mut().insert(EVENT_ID_HEADER, v);
+    }
+    resp
+}
+
+fn validate_maps(body: &IngestBody) -> Result<(), &'static str> {
+    if body.metrics.len() > MAX_METRICS {
+        return Err("too_many_metrics");
+    }
+    for v in body.metrics.values() {
+        match v {
+            _ if !v.is_finite() => return Err("non_finite_value"),
+            MetricValue::Array(vs) if vs.len() > MAX_ARRAY_LEN => return Err("array_too_long"),
+            MetricValue::String(s) if s.len() > MAX_STRING_LEN => return Err("string_too_long"),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Validate one body and push it into the pipeline queue. Shared by every
+/// ingest front end so validation, dedupe, backpressure and metrics stay identical.
+/// `bytes` is the body's share of the payload after decompression; it is what
+/// the event holds against `max_inflight_bytes`.
+pub fn submit(
+    st: &AppState,
+    device_id: &str,
+    mut body: IngestBody,
+    bytes: usize,
+) -> Result<Accepted, IngestError> {
+    if !st.ready.is_ready(&st.cfg.health) {
+        return Err(IngestError::NotReady);
+    }
+
+    if let Err(reason) = st.decoders.apply(device_id, &mut body) {
+        st.metrics.ingest_rejected_total(reason);
+        return Err(IngestError::Invalid(reason));
+    }
+
+    if let Err(reason) = validate_maps(&body) {
+        st.metrics.ingest_rejected_total(reason);
+        return Err(IngestError::Invalid(reason));
+    }
+
+    if let Err(reason) = st.registry.check(&body.metrics) {
+        st.metrics.ingest_rejected_total(reason);
+        return Err(IngestError::Invalid(reason));
+    }
+
+    // Classify and record `seq` under one lock so concurrent copies can't both
+    // pass; undone below if the event doesn't make it into the queue.
+    let observed = body.seq.map(|seq| (seq, st.seq.observe(device_id, seq)));
+    if let Some((_, SeqOutcome::Duplicate)) = observed
+        && st.cfg.sequence.drop_duplicates
+    {
+        st.metrics.seq_outcome(SeqOutcome::Duplicate);
+        return Ok(Accepted::Duplicate);
+    }
+    let rollback = || {
+        if let Some((seq, outcome)) = observed {
+            st.seq.rollback(device_id, seq, outcome);
+        }
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let ts = match st.clock.resolve(device_id, body.ts, now) {
+        Ok((ts, action)) => {
+            st.metrics.ts_action(action);
+            ts
+        }
+        Err(reason) => {
+            rollback();
+            st.metrics.ingest_rejected_total(reason);
+            return Err(IngestError::Invalid(reason));
+        }
+    };
+
+    let event = Event {
+        id: Ulid::new(),
+        device_id: device_id.to_string(),
+        ts,
+        seq: body.seq,
+        units: st.registry.units(&body.metrics, body.units),
+        metrics: body.metrics,
+        tags: body.tags,
+        payload: body.payload,
+        received_at: now,
+        bytes,
+    };
+
+    if !st.inflight.try_acquire(bytes) {
+        rollback();
+        st.metrics.ingest_rejected_total("inflight_bytes");
+        return Err(IngestError::InflightBytes);
+    }
+    st.metrics.inflight_bytes(st.inflight.used());
+    let id = event.id;
+    match st.ingest_tx.try_send(event) {
+        Ok(_) => {
+            if let Some((_, outcome)) = observed {
+                st.metrics.seq_outcome(outcome);
+            }
+            tracing::debug!(event_id = %id, device_id, "event enqueued");
+            Ok(Accepted::Enqueued(id))
+        }
+        Err(_) => {
+            rollback();
+            st.inflight.release(bytes);
+            Err(IngestError::QueueFull)
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Prometheus text exposition format pushed by a device. Samples sharing a label
+//! set (and timestamp) become one event, labels become tags, and the number of
+//! distinct label sets per device is capped to keep tag cardinality in check.
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use time::OffsetDateTime;
+
+use crate::app::AppState;
+use crate::device_map::DeviceMap;
+use crate::domain::MetricValue;
+use crate::ingest::batch::respond;
+use crate::ingest::encoding::decode_body;
+use crate::ingest::pipeline::{self, IngestError, MAX_METRICS};
+use crate::ingest::types::{BatchResponse, ErrorBody, IngestBody};
+
+type Labels = BTreeMap<String, String>;
+
+/// Remembers the label sets seen per device and refuses new ones past the cap.
+pub struct SeriesLimiter {
+    max_per_device: usize,
+    devices: Mutex<DeviceMap<HashSet<u64>>>,
+}
+
+impl SeriesLimiter {
+    pub fn new(max_per_device: usize, max_devices: usize) -> Self {
+        Self {
+            max_per_device,
+            devices: Mutex::new(DeviceMap::new(max_devices)),
+        }
+    }
+
+    /// Whether `labels` is a known label set for the device or still fits under the cap.
+    pub fn admit(&self, device_id: &str, labels: &Labels) -> bool {
+        let mut h = DefaultHasher::new();
+        labels.hash(&mut h);
+        let key = h.finish();
+
+        let mut devices = self.devices.lock().unwrap();
+        let seen = devices.touch(device_id);
+        if seen.contains(&key) {
+            return true;
+        }
+        if seen.len() >= self.max_per_device {
+            return false;
+        }
+        seen.insert(key);
+        true
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Sample {
+    name: String,
+    labels: Labels,
+    value: f64,
+    ts_ms: Option<i64>,
+}
+
+fn parse_sample(line: &str) -> Result<Sample, String> {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .ok_or_else(|| format!("missing value in `{line}`"))?;
+    let name = &line[..name_end];
+    if name.is_empty() {
+        return Err(format!("missing metric name in `{line}`"));
+    }
+    let mut rest = &line[name_end..];
+    let mut labels = BTreeMap::new();
+    if let Some(after) = rest.strip_prefix('{') {
+        rest = parse_labels(after, &mut labels)?;
+    }
+
+    let mut parts = rest.split_whitespace();
+    let value = parts
+        .next()
+        .ok_or_else(|| format!("missing value for `{name}`"))?;
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for `{name}`"))?;
+    let ts_ms = parts
+        .next()
+        .map(|ts| ts.parse().map_err(|_| format!("invalid timestamp `{ts}`")))
+        .transpose()?;
+    if parts.next().is_some() {
+        return Err(format!("trailing data after `{name}` sample"));
+    }
+    Ok(Sample {
+        name: name.to_string(),
+        labels,
+        value,
+        ts_ms,
+    })
+}
+
+/// Parses `name="value",...}` and returns what follows the closing brace.
+fn parse_labels<'a>(mut s: &'a str, labels: &mut Labels) -> Result<&'a str, String> {
+    loop {
+        s = s.trim_start();
+        if let Some(rest) = s.strip_prefix('}') {
+            return Ok(rest);
+        }
+        let (name, rest) = s.split_once('=').ok_or("expected label=\"value\"")?;
+        let rest = rest
+            .trim_start()
+            .strip_prefix('"')
+            .ok_or("label value must be quoted")?;
+
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((i, '"')) => break i,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, c)) => value.push(c),
+                    None => return Err("unterminated label value".into()),
+                },
+                Some((_, c)) => value.push(c),
+                None => return Err("unterminated label value".into()),
+            }
+        };
+        labels.insert(name.trim().to_string(), value);
+        s = rest[end + 1..].trim_start();
+        s = s.strip_prefix(',').unwrap_or(s);
+    }
+}
+
+/// Groups samples by label set and timestamp, at most `MAX_METRICS` metrics per body.
+fn into_bodies(samples: Vec<Sample>) -> Vec<(Labels, IngestBody)> {
+    // Keyed by label set and timestamp (ms).
+    let mut groups: BTreeMap<(Labels, Option<i64>), Vec<_>> = BTreeMap::new();
+    for s in samples {
+        groups
+            .entry((s.labels, s.ts_ms))
+            .or_default()
+            .push((s.name, s.value));
+    }
+
+    let mut bodies = Vec::new();
+    for ((labels, ts_ms), metrics) in groups {
+        let ts = ts_ms.and_then(|ms| {
+            OffsetDateTime::from_unix_timestamp_nanos(i128::from(ms) * 1_000_000).ok()
+        });
+        for chunk in metrics.chunks(MAX_METRICS) {
+            bodies.push((
+                labels.clone(),
+                IngestBody {
+                    ts,
+                    seq: None,
+                    metrics: chunk
+                        .iter()
+                        .map(|(name, v)| (name.clone(), MetricValue::Float(*v)))
+                        .collect(),
+                    tags: labels.clone(),
+                    units: BTreeMap::new(),
+                    payload: serde_json::Value::Null,
+                },
+            ));
+        }
+    }
+    bodies
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/ingest/{device_id}/prometheus",
+    request_body(
+        description = "Prometheus text exposition format (0.0.4); labels become tags, NaN/Inf samples are skipped",
+        content(("text/plain"))
+    ),
+    params(
+        ("device_id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 202, description = "Body processed; one result per event, label sets over the cap are rejected with `series_limit`", body = BatchResponse),
+        (status = 400, description = "Malformed exposition body", body = ErrorBody),
+        (status = 413, description = "Too many events or body too large"),
+        (status = 415, description = "Unsupported Content-Encoding"),
+        (status = 503, description = "Not accepting"),
+    ),
+    tag = "ingest"
+)]
+pub async fn ingest_prometheus(
+    State(st): State<AppState>,
+    Path(device_id): Path<String>,
+    headers: HeaderMap,
+    raw: Bytes,
+) -> Response {
+    let raw = match decode_body(&st.metrics, &headers, raw, st.cfg.ingest.max_batch_bytes) {
+        Ok(raw) => raw,
+        Err(status) => return status.into_response(),
+    };
+    let Ok(text) = std::str::from_utf8(&raw) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let mut samples = Vec::new();
+    for (index, line) in text.lines().map(str::trim).enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_sample(line) {
+            Ok(s) if s.value.is_finite() => samples.push(s),
+            Ok(_) => st.metrics.prometheus_sample_skipped("non_finite"),
+            Err(message) => {
+                st.metrics.ingest_rejected_total("invalid_exposition");
+                let body = ErrorBody {
+                    code: "invalid_exposition",
+                    message,
+                    index: Some(index),
+                };
+                return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+            }
+        }
+    }
+    let bodies = into_bodies(samples);
+    if bodies.len() > st.cfg.ingest.max_batch_items {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+    if !st.ready.is_ready(&st.cfg.health) {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let bytes = raw.len() / bodies.len().max(1);
+    let outcomes = bodies
+        .into_iter()
+        .map(|(labels, body)| {
+            if !st.series.admit(&device_id, &labels) {
+                st.metrics.ingest_rejected_total("series_limit");
+                return Err(IngestError::Invalid("series_limit"));
+            }
+            pipeline::submit(&st, &device_id, body, bytes)
+        })
+        .collect();
+    respond(&st, outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_samples_with_escaped_labels() {
+        let s = parse_sample(
+            r#"http_requests_total{method="post",path="/a\"b\\c"} 1027 1395066363000"#,
+        )
+        .unwrap();
+        assert_eq!(s.name, "http_requests_total");
+        assert_eq!(s.labels["method"], "post");
+        assert_eq!(s.labels["path"], r#"/a"b\c"#);
+        assert_eq!(s.value, 1027.0);
+        assert_eq!(s.ts_ms, Some(1_395_066_363_000));
+
+        let s = parse_sample("up 1").unwrap();
+        assert!(s.labels.is_empty());
+        assert!(parse_sample("temp +Inf").unwrap().value.is_infinite());
+        assert!(parse_sample(r#"x{a="1"} "#).is_err());
+        assert!(parse_sample(r#"x{a=1} 2"#).is_err());
+    }
+
+    #[test]
+    fn groups_by_label_set_and_caps_metrics_per_body() {
+        let mut samples: Vec<Sample> = (0..MAX_METRICS + 1)
+            .map(|i| parse_sample(&format!("m{i} {i}")).unwrap())
+            .collect();
+        samples.push(parse_sample(r#"cpu_seconds{cpu="0"} 5"#).unwrap());
+        let bodies = into_bodies(samples);
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(bodies[0].1.metrics.len(), MAX_METRICS);
+        assert_eq!(bodies[2].1.tags["cpu"], "0");
+    }
+
+    #[test]
+    fn limits_label_sets_per_device() {
+        let limiter = SeriesLimiter::new(2, 100);
+        let set = |v: &str| BTreeMap::from([("cpu".to_string(), v.to_string())]);
+        assert!(limiter.admit("d1", &set("0")));
+        assert!(limiter.admit("d1", &set("1")));
+        assert!(!limiter.admit("d1", &set("2")));
+        assert!(limiter.admit("d1", &set("0")));
+        assert!(limiter.admit("d2", &set("2")));
+    }
+}
@@ -0,0 +1,201 @@
+//! Protobuf ingest messages. Hand-written prost types matching `proto/ingest.proto`
+//! so the build doesn't need `protoc`; keep the two in sync.
+
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+
+use crate::domain;
+use crate::ingest::types::{BatchItem, IngestBody};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MetricValue {
+    #[prost(oneof = "metric_value::Value", tags = "1, 2, 3, 4, 5, 6")]
+    pub value: Option<metric_value::Value>,
+}
+
+pub mod metric_value {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(double, tag = "1")]
+        DoubleValue(f64),
+        #[prost(sint64, tag = "2")]
+        IntValue(i64),
+        #[prost(bool, tag = "3")]
+        BoolValue(bool),
+        #[prost(string, tag = "4")]
+        StringValue(String),
+        #[prost(uint64, tag = "5")]
+        UintValue(u64),
+        #[prost(message, tag = "6")]
+        ArrayValue(super::DoubleArray),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DoubleArray {
+    #[prost(double, repeated, tag = "1")]
+    pub values: Vec<f64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Reading {
+    #[prost(int64, optional, tag = "1")]
+    pub ts_ms: Option<i64>,
+    #[prost(uint64, optional, tag = "2")]
+    pub seq: Option<u64>,
+    #[prost(btree_map = "string, message", tag = "3")]
+    pub metrics: BTreeMap<String, MetricValue>,
+    #[prost(btree_map = "string, string", tag = "4")]
+    pub tags: BTreeMap<String, String>,
+    #[prost(string, optional, tag = "5")]
+    pub payload_json: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub device_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IngestBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub readings: Vec<Reading>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum ItemStatus {
+    Unspecified = 0,
+    Accepted = 1,
+    Duplicate = 2,
+    Rejected = 3,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IngestReply {
+    #[prost(enumeration = "ItemStatus", tag = "1")]
+    pub status: i32,
+    #[prost(string, tag = "2")]
+    pub event_id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ItemResult {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    #[prost(enumeration = "ItemStatus", tag = "2")]
+    pub status: i32,
+    #[prost(string, optional, tag = "3")]
+    pub code: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub event_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BatchReply {
+    #[prost(uint32, tag = "1")]
+    pub accepted: u32,
+    #[prost(uint32, tag = "2")]
+    pub duplicates: u32,
+    #[prost(uint32, tag = "3")]
+    pub rejected: u32,
+    #[prost(message, repeated, tag = "4")]
+    pub results: Vec<ItemResult>,
+}
+
+impl Reading {
+    /// Map onto the JSON ingest shape.
+    pub fn into_batch_item(self) -> Result<BatchItem, &'static str> {
+        let ts = self
+            .ts_ms
+            .map(|ms| OffsetDateTime::from_unix_timestamp_nanos(ms as i128 * 1_000_000))
+            .transpose()
+            .map_err(|_| "invalid_ts")?;
+        let payload = self
+            .payload_json
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .map_err(|_| "invalid_payload")?
+            .unwrap_or_default();
+        let metrics = self
+            .metrics
+            .into_iter()
+            .map(|(k, v)| {
+                use metric_value::Value;
+                let v = match v.value.ok_or("missing_metric_value")? {
+                    Value::DoubleValue(d) => domain::MetricValue::Float(d),
+                    Value::IntValue(i) => domain::MetricValue::Int(i),
+                    Value::BoolValue(b) => domain::MetricValue::Bool(b),
+                    Value::StringValue(s) => domain::MetricValue::String(s),
+                    Value::UintValue(u) => domain::MetricValue::UInt(u),
+                    Value::ArrayValue(a) => domain::MetricValue::Array(a.values),
+                };
+                Ok((k, v))
+            })
+            .collect::<Result<_, &'static str>>()?;
+
+        Ok(BatchItem {
+            device_id: self.device_id,
+            body: IngestBody {
+                ts,
+                seq: self.seq,
+                metrics,
+                tags: self.tags,
+                units: BTreeMap::new(),
+                payload,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn reading_maps_onto_ingest_body() {
+        let mut metrics = BTreeMap::new();
+        metrics.insert(
+            "temp_c".to_string(),
+            MetricValue {
+                value: Some(metric_value::Value::DoubleValue(21.5)),
+            },
+        );
+        metrics.insert(
+            "door_open".to_string(),
+            MetricValue {
+                value: Some(metric_value::Value::BoolValue(true)),
+            },
+        );
+        metrics.insert(
+            "energy_wh".to_string(),
+            MetricValue {
+                value: Some(metric_value::Value::UintValue(u64::MAX)),
+            },
+        );
+        let reading = Reading {
+            ts_ms: Some(1_758_626_321_500),
+            seq: Some(7),
+            metrics,
+            payload_json: Some(r#"{"raw":"ok"}"#.into()),
+            ..Default::default()
+        };
+
+        let decoded = Reading::decode(reading.encode_to_vec().as_slice()).unwrap();
+        let item = decoded.into_batch_item().unwrap();
+        assert_eq!(item.body.seq, Some(7));
+        assert_eq!(item.body.metrics["temp_c"], 21.5);
+        assert_eq!(
+            item.body.metrics["door_open"],
+            domain::MetricValue::Bool(true)
+        );
+        assert_eq!(
+            item.body.metrics["energy_wh"],
+            domain::MetricValue::UInt(u64::MAX)
+        );
+        assert_eq!(item.body.payload["raw"], "ok");
+        assert_eq!(
+            item.body.ts.unwrap().unix_timestamp_nanos(),
+            1_758_626_321_500_000_000
+        );
+    }
+}
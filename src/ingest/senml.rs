@@ -0,0 +1,666 @@
+//! SenML (RFC 8428) packs, JSON or CBOR. Base fields are resolved per record and
+//! records sharing a device and time are folded into one `Event`.
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Deserialize;
+use serde_json::Number;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+
+use crate::app::AppState;
+use crate::domain::MetricValue;
+use crate::ingest::batch::respond;
+use crate::ingest::encoding::decode_body;
+use crate::ingest::handler::content_type;
+use crate::ingest::pipeline;
+use crate::ingest::types::{BatchResponse, ErrorBody, IngestBody};
+
+/// Times below 2^28 s are relative to "now" (RFC 8428 §4.5.3).
+const RELATIVE_TIME_LIMIT: f64 = (1u64 << 28) as f64;
+
+/// A SenML record with the JSON labels. CBOR records are mapped onto the same labels.
+#[derive(Debug, Default, Deserialize)]
+struct Record {
+    bn: Option<String>,
+    bt: Option<f64>,
+    bu: Option<String>,
+    bv: Option<Number>,
+    bs: Option<Number>,
+    bver: Option<i64>,
+    n: Option<String>,
+    u: Option<String>,
+    v: Option<Number>,
+    vs: Option<String>,
+    vb: Option<bool>,
+    vd: Option<String>,
+    s: Option<Number>,
+    t: Option<f64>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug)]
+struct SenmlError {
+    code: &'static str,
+    message: String,
+    index: Option<usize>,
+}
+
+impl SenmlError {
+    fn pack(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            index: None,
+        }
+    }
+
+    fn record(index: usize, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            index: Some(index),
+        }
+    }
+}
+
+impl IntoResponse for SenmlError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code,
+            message: self.message,
+            index: self.index,
+        };
+        (StatusCode::BAD_REQUEST, Json(body)).into_response()
+    }
+}
+
+/// One resolved SenML measurement.
+#[derive(Debug, PartialEq)]
+struct Resolved {
+    /// Record the measurement came from.
+    index: usize,
+    base: String,
+    name: String,
+    unit: Option<String>,
+    time: OffsetDateTime,
+    value: Value,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Number(MetricValue),
+    Sum(MetricValue),
+    Bool(bool),
+    String(String),
+    Data(String),
+}
+
+fn cbor_label(key: &ciborium::Value) -> Option<&'static str> {
+    let label = match key.as_integer().map(i128::from)? {
+        -1 => "bver",
+        -2 => "bn",
+        -3 => "bt",
+        -4 => "bu",
+        -5 => "bv",
+        -6 => "bs",
+        0 => "n",
+        1 => "u",
+        2 => "v",
+        3 => "vs",
+        4 => "vb",
+        5 => "s",
+        6 => "t",
+        7 => "ut",
+        8 => "vd",
+        _ => return None,
+    };
+    Some(label)
+}
+
+fn cbor_to_json(v: ciborium::Value) -> serde_json::Value {
+    use ciborium::Value as C;
+    match v {
+        C::Integer(i) => {
+            let i = i128::from(i);
+            if let Ok(n) = u64::try_from(i) {
+                serde_json::Value::from(n)
+            } else if let Ok(n) = i64::try_from(i) {
+                serde_json::Value::from(n)
+            } else {
+                serde_json::Value::from(i as f64)
+            }
+        }
+        C::Float(f) => serde_json::Value::from(f),
+        C::Text(s) => serde_json::Value::from(s),
+        C::Bool(b) => serde_json::Value::from(b),
+        // SenML data values travel as base64url in JSON.
+        C::Bytes(b) => serde_json::Value::from(URL_SAFE_NO_PAD.encode(b)),
+        C::Tag(_, inner) => cbor_to_json(*inner),
+        _ => serde_json::Value::Null,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PackFormat {
+    Json,
+    Cbor,
+}
+
+impl PackFormat {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        match content_type(headers)?.to_ascii_lowercase().as_str() {
+            "application/senml+json" | "application/json" => Some(PackFormat::Json),
+            "application/senml+cbor" | "application/cbor" => Some(PackFormat::Cbor),
+            _ => None,
+        }
+    }
+}
+
+fn parse_pack(format: PackFormat, raw: &[u8]) -> Result<Vec<Record>, SenmlError> {
+    match format {
+        PackFormat::Json => {
+            let values: Vec<serde_json::Value> = serde_json::from_slice(raw).map_err(|e| {
+                SenmlError::pack("invalid_pack", format!("pack is not a JSON array: {e}"))
+            })?;
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    serde_json::from_value(v)
+                        .map_err(|e| SenmlError::record(i, "invalid_record", e.to_string()))
+                })
+                .collect()
+        }
+        PackFormat::Cbor => {
+            let values: Vec<ciborium::Value> = ciborium::from_reader(raw).map_err(|e| {
+                SenmlError::pack("invalid_pack", format!("pack is not a CBOR array: {e}"))
+            })?;
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let ciborium::Value::Map(entries) = v else {
+                        return Err(SenmlError::record(
+                            i,
+                            "invalid_record",
+                            "record is not a map",
+                        ));
+                    };
+                    let mut obj = serde_json::Map::new();
+                    for (k, v) in entries {
+                        let key = match (cbor_label(&k), k) {
+                            (Some(label), _) => label.to_string(),
+                            (None, ciborium::Value::Text(s)) => s,
+                            (None, other) => format!("{other:?}"),
+                        };
+                        obj.insert(key, cbor_to_json(v));
+                    }
+                    serde_json::from_value(obj.into())
+                        .map_err(|e| SenmlError::record(i, "invalid_record", e.to_string()))
+                })
+                .collect()
+        }
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "-:./_".contains(c))
+}
+
+fn to_time(secs: f64, now: OffsetDateTime) -> Option<OffsetDateTime> {
+    if !secs.is_finite() {
+        return None;
+    }
+    if secs.abs() < RELATIVE_TIME_LIMIT {
+        return Some(now + time::Duration::seconds_f64(secs));
+    }
+    OffsetDateTime::from_unix_timestamp_nanos((secs * 1e9) as i128).ok()
+}
+
+/// `base + v`, kept exact while both are integers and the sum fits `u64`/`i64`.
+fn add(base: Option<&Number>, v: &Number) -> MetricValue {
+    let int = |n: &Number| {
+        n.as_u64()
+            .map(i128::from)
+            .or_else(|| n.as_i64().map(i128::from))
+    };
+    if let (Some(a), Some(b)) = (base.map_or(Some(0), int), int(v)) {
+        if let Ok(sum) = u64::try_from(a + b) {
+            return MetricValue::UInt(sum);
+        }
+        if let Ok(sum) = i64::try_from(a + b) {
+            return MetricValue::Int(sum);
+        }
+    }
+    let float = |n: &Number| n.as_f64().unwrap_or(f64::NAN);
+    MetricValue::Float(base.map_or(0.0, float) + float(v))
+}
+
+/// Resolve base fields into self-contained records (RFC 8428 §4.6).
+fn resolve(records: Vec<Record>, now: OffsetDateTime) -> Result<Vec<Resolved>, SenmlError> {
+    let mut bn = String::new();
+    let mut bt = 0.0;
+    let (mut bv, mut bs): (Option<Number>, Option<Number>) = (None, None);
+    let mut bu: Option<String> = None;
+    let mut out = Vec::with_capacity(records.len());
+
+    for (i, r) in records.into_iter().enumerate() {
+        if let Some(label) = r.extra.keys().find(|k| k.ends_with('_')) {
+            return Err(SenmlError::record(
+                i,
+                "unsupported_field",
+                format!("must-understand field `{label}` is not supported"),
+            ));
+        }
+        if r.bver.is_some_and(|v| v > 10) {
+            return Err(SenmlError::record(i, "unsupported_version", "bver > 10"));
+        }
+        if let Some(v) = r.bn {
+            bn = v;
+        }
+        if let Some(v) = r.bt {
+            bt = v;
+        }
+        if let Some(v) = r.bu {
+            bu = Some(v);
+        }
+        if r.bv.is_some() {
+            bv = r.bv;
+        }
+        if r.bs.is_some() {
+            bs = r.bs;
+        }
+
+        let values = [
+            r.v.is_some(),
+            r.vs.is_some(),
+            r.vb.is_some(),
+            r.vd.is_some(),
+            r.s.is_some(),
+        ];
+        let value = match (values.iter().filter(|v| **v).count(), &r.s) {
+            (0, _) => {
+                // Base-only record.
+                if r.n.is_none() && r.t.is_none() {
+                    continue;
+                }
+                return Err(SenmlError::record(
+                    i,
+                    "missing_value",
+                    "record has no value or sum",
+                ));
+            }
+            (1, Some(s)) => Value::Sum(add(bs.as_ref(), s)),
+            (1, None) => {
+                if let Some(v) = &r.v {
+                    Value::Number(add(bv.as_ref(), v))
+                } else if let Some(v) = r.vb {
+                    Value::Bool(v)
+                } else if let Some(v) = r.vs {
+                    Value::String(v)
+                } else {
+                    Value::Data(r.vd.unwrap_or_default())
+                }
+            }
+            _ => {
+                return Err(SenmlError::record(
+                    i,
+                    "multiple_values",
+                    "record has more than one value",
+                ));
+            }
+        };
+        if let Value::Number(v) | Value::Sum(v) = &value
+            && !v.is_finite()
+        {
+            return Err(SenmlError::record(i, "non_finite", "value must be finite"));
+        }
+
+        let name = r.n.unwrap_or_default();
+        let full = format!("{bn}{name}");
+        if !valid_name(&full) {
+            return Err(SenmlError::record(
+                i,
+                "invalid_name",
+                format!("resolved name `{full}` is not a valid SenML name"),
+            ));
+        }
+        let time = to_time(bt + r.t.unwrap_or(0.0), now)
+            .ok_or_else(|| SenmlError::record(i, "invalid_time", "time out of range"))?;
+
+        out.push(Resolved {
+            index: i,
+            base: bn.clone(),
+            name: if name.is_empty() { full } else { name },
+            unit: r.u.or_else(|| bu.clone()),
+            time,
+            value,
+        });
+    }
+    Ok(out)
+}
+
+/// Fold resolved records into one body per (device, time). Two records landing
+/// on the same field of one body (e.g. the same `n` under different base names
+/// when the device comes from the path) are rejected rather than overwritten.
+fn into_bodies(
+    resolved: Vec<Resolved>,
+    device_for: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, IngestBody)>, SenmlError> {
+    let mut groups: BTreeMap<(String, OffsetDateTime), IngestBody> = BTreeMap::new();
+    for r in resolved {
+        let device = device_for(&r.base).ok_or_else(|| {
+            SenmlError::record(
+                r.index,
+                "missing_base_name",
+                "base name needed to identify the device",
+            )
+        })?;
+        let body = groups
+            .entry((device, r.time))
+            .or_insert_with(|| IngestBody {
+                ts: Some(r.time),
+                seq: None,
+                metrics: BTreeMap::new(),
+                tags: BTreeMap::new(),
+                units: BTreeMap::new(),
+                payload: serde_json::Value::Null,
+            });
+        let key = match &r.value {
+            Value::Sum(_) => format!("{}_sum", r.name),
+            _ => r.name,
+        };
+        if body.metrics.contains_key(&key)
+            || body.tags.contains_key(&key)
+            || body.payload.get(&key).is_some()
+        {
+            return Err(SenmlError::record(
+                r.index,
+                "duplicate_name",
+                format!("`{key}` appears twice for the same device and time"),
+            ));
+        }
+        match r.value {
+            Value::Number(v) | Value::Sum(v) => {
+                body.metrics.insert(key.clone(), v);
+            }
+            Value::Bool(v) => {
+                body.metrics.insert(key.clone(), v.into());
+            }
+            Value::String(v) => {
+                body.tags.insert(key.clone(), v);
+            }
+            Value::Data(v) => {
+                if !body.payload.is_object() {
+                    body.payload = serde_json::Value::Object(Default::default());
+                }
+                body.payload[&key] = v.into();
+            }
+        }
+        if let Some(unit) = r.unit {
+            body.units.insert(key, unit);
+        }
+    }
+    Ok(groups
+        .into_iter()
+        .map(|((device, _), body)| (device, body))
+        .collect())
+}
+
+async fn ingest_pack(
+    st: AppState,
+    headers: HeaderMap,
+    raw: Bytes,
+    device_for: impl Fn(&str) -> Option<String>,
+) -> Response {
+    let raw = match decode_body(&st.metrics, &headers, raw, st.cfg.ingest.max_batch_bytes) {
+        Ok(raw) => raw,
+        Err(status) => return status.into_response(),
+    };
+    let Some(format) = PackFormat::from_headers(&headers) else {
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+    };
+    let records = match parse_pack(format, &raw) {
+        Ok(records) => records,
+        Err(e) => {
+            st.metrics.ingest_rejected_total("invalid_senml");
+            return e.into_response();
+        }
+    };
+    if records.len() > st.cfg.ingest.max_batch_items {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+    let bodies = match resolve(records, OffsetDateTime::now_utc())
+        .and_then(|resolved| into_bodies(resolved, device_for))
+    {
+        Ok(bodies) => bodies,
+        Err(e) => {
+            st.metrics.ingest_rejected_total("invalid_senml");
+            return e.into_response();
+        }
+    };
+    if !st.ready.is_ready(&st.cfg.health) {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let bytes = raw.len() / bodies.len().max(1);
+    let outcomes = bodies
+        .into_iter()
+        .map(|(device_id, body)| pipeline::submit(&st, &device_id, body, bytes))
+        .collect();
+    respond(&st, outcomes)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/ingest/{device_id}/senml",
+    request_body(
+        description = "SenML pack (RFC 8428); records sharing a time become one event",
+        content(("application/senml+json"), ("application/senml+cbor"))
+    ),
+    params(
+        ("device_id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 202, description = "Pack processed; one result per event", body = BatchResponse),
+        (status = 400, description = "Malformed pack", body = ErrorBody),
+        (status = 413, description = "Too many records or body too large"),
+        (status = 415, description = "Unsupported Content-Type or Content-Encoding"),
+        (status = 503, description = "Not accepting"),
+    ),
+    tag = "ingest"
+)]
+pub async fn ingest_senml(
+    State(st): State<AppState>,
+    Path(device_id): Path<String>,
+    headers: HeaderMap,
+    raw: Bytes,
+) -> Response {
+    ingest_pack(st, headers, raw, |_| Some(device_id.clone())).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/ingest/senml",
+    request_body(
+        description = "SenML pack (RFC 8428); the base name (`bn`, trailing separator stripped) is the device id",
+        content(("application/senml+json"), ("application/senml+cbor"))
+    ),
+    responses(
+        (status = 202, description = "Pack processed; one result per event", body = BatchResponse),
+        (status = 400, description = "Malformed pack", body = ErrorBody),
+        (status = 413, description = "Too many records or body too large"),
+        (status = 415, description = "Unsupported Content-Type or Content-Encoding"),
+        (status = 503, description = "Not accepting"),
+    ),
+    tag = "ingest"
+)]
+pub async fn ingest_senml_by_base_name(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    raw: Bytes,
+) -> Response {
+    ingest_pack(st, headers, raw, |bn| {
+        let device = bn.trim_end_matches([':', '/', '.', '-', '_']);
+        (!device.is_empty()).then(|| device.to_string())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(json: &str) -> Vec<Record> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn now() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_758_628_800).unwrap()
+    }
+
+    #[test]
+    fn resolves_base_fields_and_groups_by_time() {
+        let pack = records(
+            r#"[
+                {"bn":"urn:dev:ow:10e2073a01080063:","bt":1.276020076e+09,"bu":"A","bver":5,
+                 "n":"voltage","u":"V","v":120.1},
+                {"n":"current","t":-5,"v":1.2},
+                {"n":"current","t":-4,"v":1.3},
+                {"n":"status","vs":"ok","t":-4}
+            ]"#,
+        );
+        let resolved = resolve(pack, now()).unwrap();
+        let bodies = into_bodies(resolved, |bn| Some(bn.trim_end_matches(':').into())).unwrap();
+
+        assert_eq!(bodies.len(), 3);
+        let (device, body) = &bodies[2];
+        assert_eq!(device, "urn:dev:ow:10e2073a01080063");
+        assert_eq!(body.metrics["voltage"], 120.1);
+        assert_eq!(body.units["voltage"], "V");
+        assert_eq!(body.ts.unwrap().unix_timestamp(), 1_276_020_076);
+
+        let (_, body) = &bodies[1];
+        assert_eq!(body.metrics["current"], 1.3);
+        assert_eq!(body.units["current"], "A");
+        assert_eq!(body.tags["status"], "ok");
+    }
+
+    #[test]
+    fn integer_values_and_sums_stay_exact() {
+        let pack = records(
+            r#"[
+                {"bn":"dev1/","bs":9007199254740993,"n":"energy","s":2},
+                {"bv":10,"n":"delta","v":-12},
+                {"n":"temp","v":21.5}
+            ]"#,
+        );
+        let resolved = resolve(pack, now()).unwrap();
+        assert_eq!(
+            resolved[0].value,
+            Value::Sum(MetricValue::UInt(9_007_199_254_740_995))
+        );
+        assert_eq!(resolved[1].value, Value::Number(MetricValue::Int(-2)));
+        assert_eq!(resolved[2].value, Value::Number(MetricValue::Float(31.5)));
+    }
+
+    #[test]
+    fn rejects_names_colliding_across_base_names() {
+        let pack = records(
+            r#"[
+                {"bn":"boiler:","bt":1.7e9,"n":"temp","v":60},
+                {"bn":"room:","n":"temp","v":21.5},
+                {"bn":"room:","n":"energy","s":5},
+                {"n":"energy_sum","v":1}
+            ]"#,
+        );
+        let path_device = |_: &str| Some("dev1".to_string());
+        let err = into_bodies(resolve(pack, now()).unwrap(), path_device).unwrap_err();
+        assert_eq!((err.code, err.index), ("duplicate_name", Some(1)));
+
+        let pack = records(
+            r#"[
+                {"bn":"room:","bt":1.7e9,"n":"energy","s":5},
+                {"n":"energy_sum","v":1}
+            ]"#,
+        );
+        let err = into_bodies(resolve(pack, now()).unwrap(), path_device).unwrap_err();
+        assert_eq!((err.code, err.index), ("duplicate_name", Some(1)));
+    }
+
+    #[test]
+    fn relative_time_is_anchored_to_now() {
+        let resolved = resolve(records(r#"[{"n":"temp","v":21.5,"t":-60}]"#), now()).unwrap();
+        assert_eq!(resolved[0].time, now() - time::Duration::seconds(60));
+    }
+
+    #[test]
+    fn rejects_malformed_records_with_index() {
+        let err = resolve(
+            records(r#"[{"n":"a","v":1},{"n":"b","v":1,"vs":"x"}]"#),
+            now(),
+        )
+        .unwrap_err();
+        assert_eq!((err.code, err.index), ("multiple_values", Some(1)));
+
+        let err = resolve(records(r#"[{"n":"a","v":1,"s":2}]"#), now()).unwrap_err();
+        assert_eq!((err.code, err.index), ("multiple_values", Some(0)));
+
+        let err = resolve(records(r#"[{"n":"a b","v":1}]"#), now()).unwrap_err();
+        assert_eq!(err.code, "invalid_name");
+
+        let err = resolve(records(r#"[{"n":"a","v":1,"foo_":1}]"#), now()).unwrap_err();
+        assert_eq!(err.code, "unsupported_field");
+    }
+
+    #[test]
+    fn cbor_pack_uses_integer_labels() {
+        use ciborium::Value as C;
+        let pack = C::Array(vec![
+            C::Map(vec![
+                (C::Integer((-2).into()), C::Text("dev1/".into())),
+                (C::Integer(0.into()), C::Text("temp".into())),
+                (C::Integer(2.into()), C::Float(21.5)),
+            ]),
+            C::Map(vec![
+                (C::Integer(0.into()), C::Text("raw".into())),
+                (C::Integer(8.into()), C::Bytes(vec![0xfb, 0xff])),
+            ]),
+        ]);
+        let mut raw = Vec::new();
+        ciborium::into_writer(&pack, &mut raw).unwrap();
+
+        let recs = parse_pack(PackFormat::Cbor, &raw).unwrap();
+        let resolved = resolve(recs, now()).unwrap();
+        assert_eq!(resolved[0].base, "dev1/");
+        assert_eq!(resolved[0].value, Value::Number(21.5.into()));
+        assert_eq!(resolved[1].value, Value::Data("-_8".into()));
+    }
+
+    #[test]
+    fn cbor_integers_stay_integers() {
+        use ciborium::Value as C;
+        let pack = C::Array(vec![C::Map(vec![
+            (C::Integer((-1).into()), C::Integer(10.into())),
+            (C::Integer((-2).into()), C::Text("dev1/".into())),
+            (C::Integer(0.into()), C::Text("count".into())),
+            (C::Integer(2.into()), C::Integer(7.into())),
+        ])]);
+        let mut raw = Vec::new();
+        ciborium::into_writer(&pack, &mut raw).unwrap();
+
+        let recs = parse_pack(PackFormat::Cbor, &raw).unwrap();
+        assert_eq!(recs[0].bver, Some(10));
+        let resolved = resolve(recs, now()).unwrap();
+        assert_eq!(resolved[0].value, Value::Number(MetricValue::UInt(7)));
+    }
+}
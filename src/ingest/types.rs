@@ -1,18 +1,135 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use time::OffsetDateTime;
+use ulid::Ulid;
 use utoipa::ToSchema;
 
-#[derive(Debug, Deserialize, ToSchema)]
+use crate::domain::MetricValue;
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct IngestBody {
-    #[serde(default)]
+    /// RFC 3339 string, or Unix seconds as a number (handy for CBOR/MessagePack).
+    #[serde(default, deserialize_with = "wire_ts::deserialize")]
     pub ts: Option<OffsetDateTime>,
     #[serde(default)]
     pub seq: Option<u64>,
+    /// Numbers, booleans, strings (states) or numeric arrays (spectra).
     #[serde(default)]
-    pub metrics: BTreeMap<String, f64>,
+    pub metrics: BTreeMap<String, MetricValue>,
     #[serde(default)]
     pub tags: BTreeMap<String, String>,
+    /// Units by metric name as sent by the source; descriptor units take precedence.
+    #[serde(default)]
+    pub units: BTreeMap<String, String>,
     #[serde(default)]
     pub payload: serde_json::Value,
 }
+
+/// One entry of a multi-device batch: an `IngestBody` plus the device it belongs to.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchItem {
+    pub device_id: Option<String>,
+    #[serde(flatten)]
+    pub body: IngestBody,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    Accepted,
+    Duplicate,
+    Rejected,
+}
+
+/// Response body of a single-event ingest.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IngestAck {
+    pub status: ItemStatus,
+    /// ULID assigned to the event (also in `X-Event-Id`); absent for duplicates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "01JAB3K4Z6X8V2N5Q7R9T1W3Y5")]
+    pub event_id: Option<Ulid>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ItemResult {
+    pub index: usize,
+    pub status: ItemStatus,
+    /// ULID assigned to the event, when accepted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub event_id: Option<Ulid>,
+    /// Rejection reason, e.g. `too_many_metrics` or `queue_full`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct BatchResponse {
+    pub accepted: usize,
+    pub duplicates: usize,
+    pub rejected: usize,
+    pub results: Vec<ItemResult>,
+}
+
+/// Structured rejection for ingest adapters that can pinpoint what is wrong.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    /// Offending record/item, when the error is about one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+}
+
+mod wire_ts {
+    use serde::Deserialize;
+    use serde::de::{self, Deserializer, Visitor};
+    use std::fmt;
+    use time::OffsetDateTime;
+    use time::format_description::well_known::Rfc3339;
+
+    struct WireTs(OffsetDateTime);
+
+    impl<'de> Deserialize<'de> for WireTs {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            d.deserialize_any(WireTsVisitor).map(WireTs)
+        }
+    }
+
+    struct WireTsVisitor;
+
+    impl Visitor<'_> for WireTsVisitor {
+        type Value = OffsetDateTime;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an RFC 3339 timestamp or Unix seconds")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            OffsetDateTime::parse(v, &Rfc3339).map_err(E::custom)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            OffsetDateTime::from_unix_timestamp(v).map_err(E::custom)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            let v = i64::try_from(v).map_err(E::custom)?;
+            self.visit_i64(v)
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            if !v.is_finite() {
+                return Err(E::custom("non-finite timestamp"));
+            }
+            OffsetDateTime::from_unix_timestamp_nanos((v * 1e9) as i128).map_err(E::custom)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Option<OffsetDateTime>, D::Error> {
+        Ok(Option::<WireTs>::deserialize(d)?.map(|w| w.0))
+    }
+}
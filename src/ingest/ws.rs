@@ -0,0 +1,121 @@
+//! Streaming ingest over one WebSocket per device: each frame is an ingest body
+//! (JSON text, or CBOR binary) and is answered with an ack frame in order.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::response::Response;
+use serde::Serialize;
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+use crate::app::AppState;
+use crate::ingest::format::BodyFormat;
+use crate::ingest::pipeline::{self, Accepted, IngestError};
+
+/// How long a client should wait before resending a frame refused for backpressure.
+const RETRY_AFTER_MS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WsStatus {
+    Accepted,
+    Duplicate,
+    Rejected,
+    /// Not taken because of backpressure; resend after `retry_after_ms`.
+    Retry,
+}
+
+/// Sent for every data frame, in the order the frames arrived.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WsAck {
+    /// 0-based index of the frame on this connection.
+    pub frame: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    pub status: WsStatus,
+    /// ULID assigned to the event, when accepted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub event_id: Option<Ulid>,
+    /// Rejection or backpressure reason, e.g. `invalid_body` or `queue_full`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/ingest/{device_id}/ws",
+    params(
+        ("device_id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 101, description = "Switching to WebSocket; send ingest bodies as JSON text or CBOR binary frames, receive one `WsAck` text frame per data frame", body = WsAck),
+        (status = 400, description = "Not a WebSocket upgrade request"),
+    ),
+    tag = "ingest"
+)]
+pub async fn ingest_ws(
+    State(st): State<AppState>,
+    Path(device_id): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.max_message_size(st.cfg.ingest.max_payload_bytes)
+        .on_upgrade(move |socket| session(socket, st, device_id))
+}
+
+async fn session(mut socket: WebSocket, st: AppState, device_id: String) {
+    st.metrics.ws_connection(1);
+    let mut frame = 0;
+    while let Some(Ok(msg)) = socket.recv().await {
+        let (format, raw) = match &msg {
+            Message::Text(text) => (BodyFormat::Json, text.as_bytes()),
+            Message::Binary(bytes) => (BodyFormat::Cbor, bytes.as_slice()),
+            Message::Close(_) => break,
+            // Pings are answered by axum.
+            _ => continue,
+        };
+        let ack = handle(&st, &device_id, format, raw, frame);
+        frame += 1;
+        st.metrics.ws_frame(ack.status);
+        let Ok(json) = serde_json::to_string(&ack) else {
+            break;
+        };
+        if socket.send(Message::Text(json)).await.is_err() {
+            break;
+        }
+    }
+    st.metrics.ws_connection(-1);
+}
+
+fn handle(st: &AppState, device_id: &str, format: BodyFormat, raw: &[u8], frame: u64) -> WsAck {
+    let mut ack = WsAck {
+        frame,
+        seq: None,
+        status: WsStatus::Rejected,
+        event_id: None,
+        code: None,
+        retry_after_ms: None,
+    };
+    let Ok(body) = format.decode_ingest(raw) else {
+        st.metrics.ingest_rejected_total("invalid_body");
+        ack.code = Some("invalid_body");
+        return ack;
+    };
+    ack.seq = body.seq;
+    match pipeline::submit(st, device_id, body, raw.len()) {
+        Ok(Accepted::Enqueued(id)) => {
+            ack.status = WsStatus::Accepted;
+            ack.event_id = Some(id);
+        }
+        Ok(Accepted::Duplicate) => ack.status = WsStatus::Duplicate,
+        Err(e @ IngestError::Invalid(_)) => ack.code = Some(e.code()),
+        Err(e) => {
+            ack.status = WsStatus::Retry;
+            ack.code = Some(e.code());
+            ack.retry_after_ms = Some(RETRY_AFTER_MS);
+        }
+    }
+    ack
+}
@@ -1,10 +1,23 @@
+pub mod admin;
 pub mod app;
+pub mod broker;
+pub mod clock;
+pub mod coap;
 pub mod config;
+pub mod device_map;
 pub mod dispatcher;
 pub mod domain;
 pub mod fanout;
+pub mod grpc;
 pub mod http;
 pub mod ingest;
+pub mod lines;
 pub mod metrics;
+pub mod modbus;
+pub mod mqtt_ingress;
 pub mod readiness;
+pub mod registry;
+pub mod sequence;
+pub mod serial;
 pub mod sink;
+pub mod timesync;
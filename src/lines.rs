@@ -0,0 +1,273 @@
+//! Plain TCP/UDP line ingest for legacy gateways and scripts. Each line is either
+//! a JSON object (a batch item: `IngestBody` plus optional `device_id`) or
+//! whitespace separated `key=value` pairs, e.g.
+//! `device_id=dev1 seq=12 ts=1758628800 temp_c=21.5 site=lab`.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::Semaphore;
+
+use crate::app::AppState;
+use crate::config::LinesCfg;
+use crate::domain::MetricValue;
+use crate::ingest::pipeline::{self, IngestError};
+use crate::ingest::types::{BatchItem, IngestBody};
+
+/// Parses one line into its optional device id and body.
+pub fn parse_line(line: &str) -> Result<(Option<String>, IngestBody), &'static str> {
+    if line.starts_with('{') {
+        let item: BatchItem = serde_json::from_str(line).map_err(|_| "invalid_body")?;
+        return Ok((item.device_id, item.body));
+    }
+
+    let mut device_id = None;
+    let mut body = IngestBody {
+        ts: None,
+        seq: None,
+        metrics: BTreeMap::new(),
+        tags: BTreeMap::new(),
+        units: BTreeMap::new(),
+        payload: serde_json::Value::Null,
+    };
+    for pair in line.split_whitespace() {
+        let Some((key, value)) = pair.split_once('=') else {
+            return Err("invalid_line");
+        };
+        match key {
+            "" => return Err("invalid_line"),
+            "device_id" => device_id = Some(value.to_string()),
+            "seq" => body.seq = Some(value.parse().map_err(|_| "invalid_seq")?),
+            "ts" => body.ts = Some(parse_ts(value).ok_or("invalid_ts")?),
+            _ => match MetricValue::parse_number(value) {
+                Some(v) => {
+                    body.metrics.insert(key.to_string(), v);
+                }
+                None => {
+                    body.tags.insert(key.to_string(), value.to_string());
+                }
+            },
+        }
+    }
+    Ok((device_id, body))
+}
+
+/// RFC 3339, or Unix seconds like the JSON `ts`.
+fn parse_ts(value: &str) -> Option<OffsetDateTime> {
+    match value.parse::<i64>() {
+        Ok(secs) => OffsetDateTime::from_unix_timestamp(secs).ok(),
+        Err(_) => OffsetDateTime::parse(value, &Rfc3339).ok(),
+    }
+}
+
+/// Label for `line_ingest_total{result}`.
+fn ingest_line(
+    st: &AppState,
+    devices: &BTreeMap<IpAddr, String>,
+    peer: IpAddr,
+    line: &[u8],
+) -> &'static str {
+    let fallback = devices.get(&peer.to_canonical()).map(String::as_str);
+    submit_line(st, line, fallback, parse_line)
+}
+
+/// Parses and submits one line, using `fallback` when it names no device; shared
+/// with the serial source. Returns the `line_ingest_total{result}` label.
+pub(crate) fn submit_line(
+    st: &AppState,
+    line: &[u8],
+    fallback: Option<&str>,
+    parse: impl FnOnce(&str) -> Result<(Option<String>, IngestBody), &'static str>,
+) -> &'static str {
+    let line = line.trim_ascii();
+    if line.is_empty() {
+        return "empty";
+    }
+    if line.len() > st.cfg.ingest.max_payload_bytes {
+        st.metrics.ingest_rejected_total("payload_too_large");
+        return "rejected";
+    }
+    let parsed = std::str::from_utf8(line)
+        .map_err(|_| "invalid_body")
+        .and_then(parse);
+    let (device_id, body) = match parsed {
+        Ok(parsed) => parsed,
+        Err(reason) => {
+            st.metrics.ingest_rejected_total(reason);
+            return "rejected";
+        }
+    };
+    let Some(device_id) = device_id.or_else(|| fallback.map(str::to_string)) else {
+        st.metrics.ingest_rejected_total("missing_device_id");
+        return "rejected";
+    };
+    match pipeline::submit(st, &device_id, body, line.len()) {
+        Ok(_) => "accepted",
+        Err(IngestError::Invalid(_)) => "rejected",
+        Err(_) => "unavailable",
+    }
+}
+
+/// Pause between attempts while the pipeline is backed up.
+const BACKPRESSURE_PAUSE: Duration = Duration::from_millis(100);
+
+pub async fn serve_tcp(bind: SocketAddr, cfg: Arc<LinesCfg>, st: AppState) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(bind).await?;
+    tracing::info!(addr = %listener.local_addr()?, "tcp line ingest listening");
+    let slots = Arc::new(Semaphore::new(cfg.max_connections));
+    let stopped = st.ready.stopped();
+    tokio::pin!(stopped);
+    loop {
+        let accepted = tokio::select! {
+            r = listener.accept() => r,
+            _ = &mut stopped => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!(error = %e, "tcp line ingest accept failed");
+                continue;
+            }
+        };
+        let Ok(slot) = slots.clone().try_acquire_owned() else {
+            tracing::warn!(%peer, "tcp line ingest at max_connections, closing");
+            continue;
+        };
+        let (cfg, st) = (cfg.clone(), st.clone());
+        tokio::spawn(async move {
+            let _slot = slot;
+            let result = tokio::select! {
+                r = tcp_session(stream, peer, &cfg, &st) => r,
+                _ = st.ready.stopped() => Ok(()),
+            };
+            if let Err(e) = result {
+                tracing::debug!(%peer, error = %e, "tcp line session closed");
+            }
+        });
+    }
+    tracing::info!("tcp line ingest stopped");
+    Ok(())
+}
+
+async fn tcp_session(
+    stream: TcpStream,
+    peer: SocketAddr,
+    cfg: &LinesCfg,
+    st: &AppState,
+) -> anyhow::Result<()> {
+    let idle = Duration::from_secs(cfg.idle_timeout_s);
+    let max = st.cfg.ingest.max_payload_bytes;
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let mut limited = (&mut reader).take(max as u64 + 1);
+        let n = tokio::time::timeout(idle, limited.read_until(b'\n', &mut line)).await??;
+        if n == 0 {
+            return Ok(());
+        }
+        let result = if line.len() > max && line.last() != Some(&b'\n') {
+            skip_line(&mut reader).await?;
+            st.metrics.ingest_rejected_total("payload_too_large");
+            "rejected"
+        } else {
+            let mut result = ingest_line(st, &cfg.devices, peer.ip(), &line);
+            // Stop reading while the pipeline is backed up so TCP flow control
+            // pushes back on the sender instead of the line being dropped.
+            while result == "unavailable" {
+                tokio::time::sleep(BACKPRESSURE_PAUSE).await;
+                result = ingest_line(st, &cfg.devices, peer.ip(), &line);
+            }
+            result
+        };
+        st.metrics.line_ingest("tcp", result);
+    }
+}
+
+/// Discards the rest of an over-long line without buffering it.
+async fn skip_line<R: AsyncBufRead + Unpin>(r: &mut R) -> std::io::Result<()> {
+    loop {
+        let buf = r.fill_buf().await?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        match buf.iter().position(|b| *b == b'\n') {
+            Some(i) => {
+                r.consume(i + 1);
+                return Ok(());
+            }
+            None => {
+                let n = buf.len();
+                r.consume(n);
+            }
+        }
+    }
+}
+
+/// One datagram may carry several lines; there is no way to push back, so lines
+/// that hit backpressure are dropped and counted.
+pub async fn serve_udp(bind: SocketAddr, cfg: Arc<LinesCfg>, st: AppState) -> anyhow::Result<()> {
+    let sock = UdpSocket::bind(bind).await?;
+    tracing::info!(addr = %sock.local_addr()?, "udp line ingest listening");
+    let mut buf = vec![0u8; 64 * 1024];
+    let stopped = st.ready.stopped();
+    tokio::pin!(stopped);
+    loop {
+        let received = tokio::select! {
+            r = sock.recv_from(&mut buf) => r,
+            _ = &mut stopped => break,
+        };
+        // Errors such as ICMP-driven ECONNREFUSED only concern one datagram.
+        let (n, peer) = match received {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!(error = %e, "udp line ingest receive failed");
+                st.metrics.line_ingest("udp", "io_error");
+                continue;
+            }
+        };
+        for line in buf[..n].split(|b| *b == b'\n') {
+            let result = ingest_line(&st, &cfg.devices, peer.ip(), line);
+            if result != "empty" {
+                st.metrics.line_ingest("udp", result);
+            }
+        }
+    }
+    tracing::info!("udp line ingest stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_key_value_lines() {
+        let (device_id, body) =
+            parse_line("device_id=dev1 seq=12 ts=1758628800 temp_c=21.5 site=lab").unwrap();
+        assert_eq!(device_id.as_deref(), Some("dev1"));
+        assert_eq!(body.seq, Some(12));
+        assert_eq!(body.ts.unwrap().unix_timestamp(), 1758628800);
+        assert_eq!(body.metrics["temp_c"], 21.5);
+        assert_eq!(body.tags["site"], "lab");
+
+        assert_eq!(parse_line("temp_c").unwrap_err(), "invalid_line");
+        assert_eq!(parse_line("seq=-1").unwrap_err(), "invalid_seq");
+        assert_eq!(parse_line("ts=yesterday").unwrap_err(), "invalid_ts");
+    }
+
+    #[test]
+    fn parses_json_lines() {
+        let (device_id, body) =
+            parse_line(r#"{"metrics":{"temp_c":21.5},"ts":"2025-09-23T12:00:00Z"}"#).unwrap();
+        assert_eq!(device_id, None);
+        assert_eq!(body.metrics["temp_c"], 21.5);
+        assert!(body.ts.is_some());
+        assert_eq!(parse_line("{not json").unwrap_err(), "invalid_body");
+    }
+}
@@ -1,6 +1,12 @@
-use metrics::{Unit, counter, describe_counter};
+use metrics::{
+    Unit, counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram,
+};
 use std::sync::Arc;
 
+use crate::clock::TsAction;
+use crate::ingest::ws::WsStatus;
+use crate::sequence::SeqOutcome;
+
 #[derive(Clone, Default)]
 pub struct AppMetrics;
 
@@ -16,12 +22,219 @@ impl AppMetrics {
             Unit::Count,
             "Rejected ingest requests by reason"
         );
+        describe_counter!(
+            "coap_requests_total",
+            Unit::Count,
+            "CoAP requests by response code"
+        );
+        describe_gauge!(
+            "serial_port_open",
+            "1 while the configured serial port is open, by path"
+        );
+        describe_counter!(
+            "modbus_polls_total",
+            Unit::Count,
+            "Modbus register polls, by device and result"
+        );
+        describe_counter!(
+            "payload_decoded_total",
+            Unit::Count,
+            "Binary payloads expanded by a configured decoder, by decoder and result"
+        );
+        describe_counter!(
+            "prometheus_samples_skipped_total",
+            Unit::Count,
+            "Pushed Prometheus samples that were skipped, by reason"
+        );
+        describe_counter!(
+            "grpc_requests_total",
+            Unit::Count,
+            "gRPC ingest calls, by method and status code"
+        );
+        describe_gauge!(
+            "ws_ingest_connections",
+            Unit::Count,
+            "Open WebSocket ingest connections"
+        );
+        describe_counter!(
+            "ws_ingest_frames_total",
+            Unit::Count,
+            "WebSocket ingest frames, by ack status"
+        );
+        describe_counter!(
+            "line_ingest_total",
+            Unit::Count,
+            "Lines received by the TCP/UDP listeners and serial ports, by transport and result"
+        );
+        describe_gauge!(
+            "mqtt_broker_connections",
+            Unit::Count,
+            "Clients connected to the embedded MQTT broker"
+        );
+        describe_counter!(
+            "mqtt_broker_connects_total",
+            Unit::Count,
+            "CONNECT attempts on the embedded MQTT broker, by return code"
+        );
+        describe_counter!(
+            "mqtt_broker_messages_total",
+            Unit::Count,
+            "PUBLISH packets received by the embedded MQTT broker, by result"
+        );
+        describe_counter!(
+            "mqtt_ingress_messages_total",
+            Unit::Count,
+            "Messages received by the MQTT ingress, by result"
+        );
+        describe_gauge!(
+            "mqtt_ingress_connected",
+            "Whether the MQTT ingress is connected to its broker"
+        );
+        describe_counter!(
+            "ingest_compressed_bytes_total",
+            Unit::Bytes,
+            "Compressed ingest body bytes received, by encoding"
+        );
+        describe_counter!(
+            "ingest_decompressed_bytes_total",
+            Unit::Bytes,
+            "Ingest body bytes after decompression, by encoding"
+        );
+        describe_histogram!(
+            "ingest_compression_ratio",
+            "Decompressed / compressed size of ingest bodies, by encoding"
+        );
+        describe_gauge!(
+            "ingest_inflight_bytes",
+            Unit::Bytes,
+            "Body bytes of events currently queued in the pipeline"
+        );
+        describe_counter!(
+            "ingest_ts_total",
+            Unit::Count,
+            "Event timestamps by how they were resolved"
+        );
+        describe_counter!(
+            "time_sync_reports_total",
+            Unit::Count,
+            "Device-measured clock offsets reported via /v1/time"
+        );
+        describe_counter!(
+            "ingest_seq_events_total",
+            Unit::Count,
+            "Ingested events with a seq, by sequence outcome"
+        );
+        describe_counter!(
+            "ingest_seq_missing_total",
+            Unit::Count,
+            "Sequence numbers skipped by devices"
+        );
+        describe_counter!(
+            "ingest_reorder_released_total",
+            Unit::Count,
+            "Times the reorder buffer gave up on a gap, by reason"
+        );
         Arc::new(Self)
     }
 
     pub fn ingest_rejected_total(&self, reason: &'static str) {
         counter!("ingest_rejected_total", "reason" => reason).increment(1);
     }
+    pub fn coap_request(&self, code: &'static str) {
+        counter!("coap_requests_total", "code" => code).increment(1);
+    }
+    pub fn serial_port_open(&self, path: &str, open: bool) {
+        gauge!("serial_port_open", "path" => path.to_string()).set(f64::from(u8::from(open)));
+    }
+    pub fn modbus_poll(&self, device_id: &str, result: &'static str) {
+        counter!("modbus_polls_total", "device_id" => device_id.to_string(), "result" => result)
+            .increment(1);
+    }
+    pub fn payload_decoded(&self, decoder: &str, result: &'static str) {
+        counter!("payload_decoded_total", "decoder" => decoder.to_string(), "result" => result)
+            .increment(1);
+    }
+    pub fn prometheus_sample_skipped(&self, reason: &'static str) {
+        counter!("prometheus_samples_skipped_total", "reason" => reason).increment(1);
+    }
+    pub fn grpc_request(&self, method: &'static str, code: tonic::Code) {
+        let code = format!("{code:?}");
+        counter!("grpc_requests_total", "method" => method, "code" => code).increment(1);
+    }
+    pub fn ws_connection(&self, delta: i8) {
+        gauge!("ws_ingest_connections").increment(f64::from(delta));
+    }
+    pub fn ws_frame(&self, status: WsStatus) {
+        let status = match status {
+            WsStatus::Accepted => "accepted",
+            WsStatus::Duplicate => "duplicate",
+            WsStatus::Rejected => "rejected",
+            WsStatus::Retry => "retry",
+        };
+        counter!("ws_ingest_frames_total", "status" => status).increment(1);
+    }
+    pub fn line_ingest(&self, transport: &'static str, result: &'static str) {
+        counter!("line_ingest_total", "transport" => transport, "result" => result).increment(1);
+    }
+    pub fn broker_connections(&self, n: usize) {
+        gauge!("mqtt_broker_connections").set(n as f64);
+    }
+    pub fn broker_connect(&self, code: rumqttc::ConnectReturnCode) {
+        let code = match code {
+            rumqttc::ConnectReturnCode::Success => "success",
+            rumqttc::ConnectReturnCode::RefusedProtocolVersion => "refused_protocol_version",
+            rumqttc::ConnectReturnCode::BadClientId => "bad_client_id",
+            rumqttc::ConnectReturnCode::ServiceUnavailable => "service_unavailable",
+            rumqttc::ConnectReturnCode::BadUserNamePassword => "bad_username_password",
+            rumqttc::ConnectReturnCode::NotAuthorized => "not_authorized",
+        };
+        counter!("mqtt_broker_connects_total", "code" => code).increment(1);
+    }
+    pub fn broker_message(&self, result: &'static str) {
+        counter!("mqtt_broker_messages_total", "result" => result).increment(1);
+    }
+    pub fn mqtt_ingress_message(&self, result: &'static str) {
+        counter!("mqtt_ingress_messages_total", "result" => result).increment(1);
+    }
+    pub fn mqtt_ingress_connected(&self, connected: bool) {
+        gauge!("mqtt_ingress_connected").set(f64::from(u8::from(connected)));
+    }
+    pub fn decompressed(&self, encoding: &'static str, compressed: usize, decompressed: usize) {
+        counter!("ingest_compressed_bytes_total", "encoding" => encoding)
+            .increment(compressed as u64);
+        counter!("ingest_decompressed_bytes_total", "encoding" => encoding)
+            .increment(decompressed as u64);
+        if compressed > 0 {
+            histogram!("ingest_compression_ratio", "encoding" => encoding)
+                .record(decompressed as f64 / compressed as f64);
+        }
+    }
+    pub fn inflight_bytes(&self, bytes: usize) {
+        gauge!("ingest_inflight_bytes").set(bytes as f64);
+    }
+    pub fn ts_action(&self, action: TsAction) {
+        counter!("ingest_ts_total", "action" => action.as_str()).increment(1);
+    }
+    pub fn time_sync_reported(&self) {
+        counter!("time_sync_reports_total").increment(1);
+    }
+    pub fn seq_outcome(&self, outcome: SeqOutcome) {
+        let label = match outcome {
+            SeqOutcome::First => "first",
+            SeqOutcome::InOrder => "in_order",
+            SeqOutcome::Gap { missing } => {
+                counter!("ingest_seq_missing_total").increment(missing);
+                "gap"
+            }
+            SeqOutcome::Late => "late",
+            SeqOutcome::Duplicate => "duplicate",
+            SeqOutcome::Reset => "reset",
+        };
+        counter!("ingest_seq_events_total", "outcome" => label).increment(1);
+    }
+    pub fn reorder_released(&self, reason: &'static str) {
+        counter!("ingest_reorder_released_total", "reason" => reason).increment(1);
+    }
     pub fn events_received(&self) {
         counter!("gateway_events_received_total").increment(1);
     }
@@ -0,0 +1,409 @@
+//! Modbus TCP poller: reads the configured holding/input registers of a slave on
+//! an interval and submits them as one event per poll under `device_id`.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{MissedTickBehavior, timeout};
+
+use crate::app::AppState;
+use crate::config::{DecoderFieldCfg, Endian, ModbusCfg, ModbusRegisterCfg, RegisterTable};
+use crate::domain::MetricValue;
+use crate::ingest::decoder::decode_field;
+use crate::ingest::pipeline::{self, IngestError};
+use crate::ingest::types::IngestBody;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ModbusError {
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("timed out")]
+    Timeout,
+    #[error("exception code {0}")]
+    Exception(u8),
+    #[error("malformed response: {0}")]
+    Protocol(&'static str),
+    #[error("register `{0}`: {1}")]
+    Decode(String, &'static str),
+}
+
+impl ModbusError {
+    /// Label for `modbus_polls_total{result}`.
+    fn code(&self) -> &'static str {
+        match self {
+            ModbusError::Io(_) | ModbusError::Timeout => "io_error",
+            ModbusError::Exception(_) => "exception",
+            ModbusError::Protocol(_) => "protocol_error",
+            ModbusError::Decode(..) => "decode_error",
+        }
+    }
+}
+
+/// One Modbus TCP connection to a slave.
+pub struct ModbusClient {
+    stream: TcpStream,
+    unit_id: u8,
+    transaction: u16,
+    io_timeout: Duration,
+}
+
+impl ModbusClient {
+    /// `io_timeout` bounds connecting and each request.
+    pub async fn connect(
+        address: &str,
+        unit_id: u8,
+        io_timeout: Duration,
+    ) -> Result<Self, ModbusError> {
+        let stream = timeout(io_timeout, TcpStream::connect(address))
+            .await
+            .map_err(|_| ModbusError::Timeout)??;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            unit_id,
+            transaction: 0,
+            io_timeout,
+        })
+    }
+
+    /// Function 0x03 (holding) or 0x04 (input): `count` registers from `address`.
+    pub async fn read_registers(
+        &mut self,
+        table: RegisterTable,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ModbusError> {
+        timeout(self.io_timeout, self.request(table, address, count))
+            .await
+            .map_err(|_| ModbusError::Timeout)?
+    }
+
+    async fn request(
+        &mut self,
+        table: RegisterTable,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ModbusError> {
+        let function = match table {
+            RegisterTable::Holding => 0x03,
+            RegisterTable::Input => 0x04,
+        };
+        self.transaction = self.transaction.wrapping_add(1);
+
+        let mut req = [0u8; 12];
+        req[0..2].copy_from_slice(&self.transaction.to_be_bytes());
+        // [2..4] protocol id 0
+        req[4..6].copy_from_slice(&6u16.to_be_bytes());
+        req[6] = self.unit_id;
+        req[7] = function;
+        req[8..10].copy_from_slice(&address.to_be_bytes());
+        req[10..12].copy_from_slice(&count.to_be_bytes());
+        self.stream.write_all(&req).await?;
+
+        let mut mbap = [0u8; 7];
+        self.stream.read_exact(&mut mbap).await?;
+        let len = usize::from(u16::from_be_bytes([mbap[4], mbap[5]]));
+        // Unit id plus at least a function code and a byte count / exception code.
+        if !(3..=254).contains(&len) {
+            return Err(ModbusError::Protocol("bad length"));
+        }
+        let mut pdu = vec![0u8; len - 1];
+        self.stream.read_exact(&mut pdu).await?;
+        if mbap[0..2] != self.transaction.to_be_bytes() || mbap[2..4] != [0, 0] {
+            return Err(ModbusError::Protocol("transaction mismatch"));
+        }
+        if mbap[6] != self.unit_id {
+            return Err(ModbusError::Protocol("unit id mismatch"));
+        }
+
+        if pdu[0] == function | 0x80 {
+            return Err(ModbusError::Exception(pdu[1]));
+        }
+        if pdu[0] != function {
+            return Err(ModbusError::Protocol("unexpected function"));
+        }
+        let data = &pdu[2..];
+        if usize::from(pdu[1]) != data.len() || data.len() != usize::from(count) * 2 {
+            return Err(ModbusError::Protocol("bad byte count"));
+        }
+        Ok(data
+            .chunks_exact(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]))
+            .collect())
+    }
+}
+
+fn field_for(reg: &ModbusRegisterCfg) -> DecoderFieldCfg {
+    DecoderFieldCfg {
+        name: reg.name.clone(),
+        offset: 0,
+        kind: reg.kind,
+        endian: Endian::Big,
+        scale: reg.scale,
+        value_offset: reg.value_offset,
+    }
+}
+
+/// Reads and decodes every configured register once.
+pub async fn poll_once(
+    client: &mut ModbusClient,
+    cfg: &ModbusCfg,
+) -> Result<BTreeMap<String, MetricValue>, ModbusError> {
+    let mut metrics = BTreeMap::new();
+    for reg in &cfg.registers {
+        let mut words = client
+            .read_registers(reg.table, reg.address, reg.count())
+            .await?;
+        if reg.word_order == Endian::Little {
+            words.reverse();
+        }
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+        let v = decode_field(&field_for(reg), &bytes)
+            .map_err(|reason| ModbusError::Decode(reg.name.clone(), reason))?;
+        metrics.insert(reg.name.clone(), v);
+    }
+    Ok(metrics)
+}
+
+/// Polls one slave forever, reconnecting after any error.
+pub async fn run(cfg: ModbusCfg, st: AppState) {
+    let io_timeout = Duration::from_millis(cfg.timeout_ms);
+    let mut ticker = tokio::time::interval(Duration::from_millis(cfg.interval_ms));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut client: Option<ModbusClient> = None;
+
+    loop {
+        ticker.tick().await;
+
+        if client.is_none() {
+            match ModbusClient::connect(&cfg.address, cfg.unit_id, io_timeout).await {
+                Ok(c) => client = Some(c),
+                Err(e) => {
+                    tracing::warn!(device_id = %cfg.device_id, address = %cfg.address, error = %e, "modbus connect failed");
+                    st.metrics.modbus_poll(&cfg.device_id, "connect_error");
+                    continue;
+                }
+            }
+        }
+        let Some(c) = client.as_mut() else { continue };
+
+        let metrics = match poll_once(c, &cfg).await {
+            Ok(metrics) => metrics,
+            Err(e) => {
+                tracing::warn!(device_id = %cfg.device_id, error = %e, "modbus poll failed");
+                st.metrics.modbus_poll(&cfg.device_id, e.code());
+                // Exceptions are answers, so the connection is still in sync.
+                if !matches!(e, ModbusError::Exception(_)) {
+                    client = None;
+                }
+                continue;
+            }
+        };
+
+        let bytes = cfg
+            .registers
+            .iter()
+            .map(|r| usize::from(r.count()) * 2)
+            .sum();
+        let body = IngestBody {
+            ts: None,
+            seq: None,
+            metrics,
+            tags: cfg.tags.clone(),
+            units: BTreeMap::new(),
+            payload: serde_json::Value::Null,
+        };
+        let result = match pipeline::submit(&st, &cfg.device_id, body, bytes) {
+            Ok(_) => "ok",
+            Err(IngestError::Invalid(_)) => "rejected",
+            // The next poll brings a fresher reading anyway.
+            Err(_) => "dropped",
+        };
+        st.metrics.modbus_poll(&cfg.device_id, result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FieldType;
+    use tokio::net::TcpListener;
+
+    /// Minimal slave: serves functions 3 and 4 from `regs`, exception 2 when out of range.
+    async fn slave(regs: Vec<u16>) -> String {
+        slave_with(
+            move |function, start, count| match regs.get(start..start + count) {
+                Some(words) => {
+                    let mut pdu = vec![function, (count * 2) as u8];
+                    pdu.extend(words.iter().flat_map(|w| w.to_be_bytes()));
+                    pdu
+                }
+                None => vec![function | 0x80, 2],
+            },
+        )
+        .await
+    }
+
+    /// Slave answering every request with the PDU built by `respond`.
+    async fn slave_with(respond: impl Fn(u8, usize, usize) -> Vec<u8> + Send + 'static) -> String {
+        slave_frames(Duration::ZERO, move |req| {
+            let function = req[7];
+            let start = usize::from(u16::from_be_bytes([req[8], req[9]]));
+            let count = usize::from(u16::from_be_bytes([req[10], req[11]]));
+            let pdu = respond(function, start, count);
+            let mut resp = req[0..4].to_vec();
+            resp.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+            resp.push(req[6]);
+            resp.extend(pdu);
+            resp
+        })
+        .await
+    }
+
+    /// Slave answering every request, after `delay`, with the frame built by `respond`.
+    async fn slave_frames(
+        delay: Duration,
+        respond: impl Fn(&[u8; 12]) -> Vec<u8> + Send + 'static,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut req = [0u8; 12];
+            while s.read_exact(&mut req).await.is_ok() {
+                tokio::time::sleep(delay).await;
+                s.write_all(&respond(&req)).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    fn register(name: &str, address: u16, kind: FieldType) -> ModbusRegisterCfg {
+        ModbusRegisterCfg {
+            name: name.into(),
+            table: RegisterTable::Holding,
+            address,
+            kind,
+            word_order: Endian::Big,
+            scale: 1.0,
+            value_offset: 0.0,
+        }
+    }
+
+    fn modbus_cfg(address: String, registers: Vec<ModbusRegisterCfg>) -> ModbusCfg {
+        ModbusCfg {
+            device_id: "boiler-1".into(),
+            address,
+            unit_id: 1,
+            interval_ms: 1000,
+            timeout_ms: 1000,
+            tags: BTreeMap::new(),
+            registers,
+        }
+    }
+
+    #[tokio::test]
+    async fn polls_and_decodes_registers() {
+        let pressure = 2.5f32.to_bits();
+        let regs = vec![
+            (-215i16) as u16,
+            (pressure >> 16) as u16,
+            pressure as u16,
+            // 70000 as u32, low word first
+            (70000u32 & 0xffff) as u16,
+            (70000u32 >> 16) as u16,
+        ];
+        let addr = slave(regs).await;
+
+        let mut temp = register("flow_temp_c", 0, FieldType::I16);
+        temp.scale = 0.1;
+        let mut energy = register("energy_wh", 3, FieldType::U32);
+        energy.table = RegisterTable::Input;
+        energy.word_order = Endian::Little;
+        let cfg = modbus_cfg(
+            addr.clone(),
+            vec![temp, register("pressure_bar", 1, FieldType::F32), energy],
+        );
+
+        let mut client = ModbusClient::connect(&addr, 1, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let m = poll_once(&mut client, &cfg).await.unwrap();
+        assert!((m["flow_temp_c"].as_f64().unwrap() + 21.5).abs() < 1e-9);
+        assert_eq!(m["pressure_bar"], 2.5);
+        assert_eq!(m["energy_wh"], 70000.0);
+
+        // Out of range: the slave answers with an exception, the link stays usable.
+        let bad = modbus_cfg(addr, vec![register("missing", 10, FieldType::U16)]);
+        let err = poll_once(&mut client, &bad).await.unwrap_err();
+        assert!(matches!(err, ModbusError::Exception(2)));
+        assert!(poll_once(&mut client, &cfg).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn short_frames_are_protocol_errors() {
+        // A lone function code with no byte count.
+        let addr = slave_with(|function, _, _| vec![function]).await;
+        let mut client = ModbusClient::connect(&addr, 1, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let err = client
+            .read_registers(RegisterTable::Holding, 0, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ModbusError::Protocol("bad length")));
+
+        let addr = slave_with(|function, _, _| vec![function | 0x80]).await;
+        let mut client = ModbusClient::connect(&addr, 1, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let err = client
+            .read_registers(RegisterTable::Input, 0, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ModbusError::Protocol("bad length")));
+    }
+
+    #[tokio::test]
+    async fn timeout_applies_per_request() {
+        // Each answer takes 200 ms: three registers together exceed the 300 ms
+        // timeout, but no single request does.
+        let addr = slave_frames(Duration::from_millis(200), |req| {
+            let mut resp = req[0..4].to_vec();
+            resp.extend_from_slice(&[0, 5, req[6], req[7], 2, 0, 42]);
+            resp
+        })
+        .await;
+        let mut cfg = modbus_cfg(
+            addr.clone(),
+            (0..3)
+                .map(|i| register(&format!("r{i}"), i, FieldType::U16))
+                .collect(),
+        );
+        cfg.timeout_ms = 300;
+        let mut client = ModbusClient::connect(&addr, 1, Duration::from_millis(300))
+            .await
+            .unwrap();
+        let m = poll_once(&mut client, &cfg).await.unwrap();
+        assert_eq!(m.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn answer_from_another_unit_is_a_protocol_error() {
+        let addr = slave_frames(Duration::ZERO, |req| {
+            let mut resp = req[0..4].to_vec();
+            resp.extend_from_slice(&[0, 5, req[6].wrapping_add(1), req[7], 2, 0, 42]);
+            resp
+        })
+        .await;
+        let mut client = ModbusClient::connect(&addr, 1, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let err = client
+            .read_registers(RegisterTable::Holding, 0, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ModbusError::Protocol("unit id mismatch")));
+    }
+}
@@ -0,0 +1,266 @@
+//! MQTT ingress: subscribe to device telemetry on a local broker and feed it into
+//! the same pipeline as `POST /v1/ingest/{device_id}`.
+
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Publish, QoS};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+use crate::app::AppState;
+use crate::config::MqttIngressCfg;
+use crate::ingest::format::BodyFormat;
+use crate::ingest::pipeline::{self, IngestError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Single,
+    Multi,
+    DeviceId,
+}
+
+/// Topic pattern with one `{device_id}` segment, e.g. `site/+/{device_id}/telemetry`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicTemplate {
+    segments: Vec<Segment>,
+}
+
+impl TopicTemplate {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let parts: Vec<&str> = template.split('/').collect();
+        let segments: Vec<Segment> = parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| match *part {
+                "{device_id}" => Ok(Segment::DeviceId),
+                "+" => Ok(Segment::Single),
+                "#" if i == parts.len() - 1 => Ok(Segment::Multi),
+                p if p.contains(['+', '#', '{', '}']) => {
+                    anyhow::bail!("invalid topic template segment `{p}`")
+                }
+                p => Ok(Segment::Literal(p.to_string())),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        anyhow::ensure!(
+            segments.iter().filter(|s| **s == Segment::DeviceId).count() == 1,
+            "topic template needs exactly one {{device_id}} segment"
+        );
+        Ok(Self { segments })
+    }
+
+    /// The `device_id` segment of `topic`, if the topic matches the template.
+    pub fn device_id<'t>(&self, topic: &'t str) -> Option<&'t str> {
+        let mut parts = topic.split('/');
+        let mut device_id = None;
+        for seg in &self.segments {
+            match seg {
+                Segment::Multi => return device_id,
+                _ => {
+                    let part = parts.next()?;
+                    match seg {
+                        Segment::Literal(lit) if lit != part => return None,
+                        Segment::DeviceId if part.is_empty() => return None,
+                        Segment::DeviceId => device_id = Some(part),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        device_id
+    }
+}
+
+/// Publishes buffered between the eventloop and the worker that handles them.
+const WORKER_QUEUE: usize = 256;
+
+/// A received publish, tagged with the connection it arrived on.
+struct Incoming {
+    conn: u64,
+    publish: Publish,
+}
+
+pub async fn run(cfg: MqttIngressCfg, st: AppState) {
+    let template = match TopicTemplate::parse(&cfg.device_id_template) {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!(error = %e, "mqtt ingress disabled");
+            return;
+        }
+    };
+    let qos = match rumqttc::qos(cfg.qos) {
+        Ok(q) => q,
+        Err(_) => QoS::AtLeastOnce,
+    };
+
+    let mut opts = MqttOptions::new(&cfg.client_id, &cfg.host, cfg.port);
+    opts.set_keep_alive(Duration::from_secs(cfg.keep_alive_s));
+    opts.set_max_packet_size(st.cfg.ingest.max_payload_bytes + 1024, 4096);
+    // Messages are acked once handled; a persistent session makes the broker
+    // redeliver the ones left unacked after a reconnect.
+    opts.set_manual_acks(true);
+    opts.set_clean_session(false);
+    if let Some(user) = &cfg.username {
+        opts.set_credentials(user, cfg.password.clone().unwrap_or_default());
+    }
+    let (client, mut eventloop) = AsyncClient::new(opts, 64);
+
+    // Handling (and its backpressure retries) runs on its own task so polling,
+    // and with it keep-alive, never waits on the pipeline.
+    let conn = Arc::new(AtomicU64::new(0));
+    let (tx, rx) = mpsc::channel(WORKER_QUEUE);
+    tokio::spawn(worker(
+        rx,
+        client.clone(),
+        conn.clone(),
+        template,
+        cfg.backpressure_retries,
+        st.clone(),
+    ));
+
+    // Set while a disconnect requested here is pending.
+    let mut draining = false;
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!(host = %cfg.host, port = cfg.port, "mqtt ingress connected");
+                conn.fetch_add(1, Ordering::Relaxed);
+                draining = false;
+                st.metrics.mqtt_ingress_connected(true);
+                for topic in &cfg.topics {
+                    if let Err(e) = client.try_subscribe(topic, qos) {
+                        tracing::warn!(%topic, error = %e, "mqtt ingress subscribe failed");
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(_))) if draining => {}
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let incoming = Incoming {
+                    conn: conn.load(Ordering::Relaxed),
+                    publish,
+                };
+                if let Err(TrySendError::Full(dropped)) = tx.try_send(incoming) {
+                    st.metrics.mqtt_ingress_message("dropped");
+                    // Unacked QoS 1/2 messages come back after a reconnect.
+                    if dropped.publish.qos != QoS::AtMostOnce {
+                        tracing::warn!("mqtt ingress worker backed up, reconnecting");
+                        conn.fetch_add(1, Ordering::Relaxed);
+                        draining = true;
+                        let _ = client.try_disconnect();
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!(error = %e, "mqtt ingress connection error");
+                st.metrics.mqtt_ingress_connected(false);
+                tokio::time::sleep(Duration::from_millis(cfg.reconnect_delay_ms)).await;
+            }
+        }
+    }
+}
+
+/// Submits publishes in order and acks each one, or disconnects so the broker
+/// redelivers what the pipeline couldn't take.
+async fn worker(
+    mut rx: mpsc::Receiver<Incoming>,
+    client: AsyncClient,
+    conn: Arc<AtomicU64>,
+    template: TopicTemplate,
+    retries: u32,
+    st: AppState,
+) {
+    // Connection the worker gave up on; its remaining messages are redelivered.
+    let mut abandoned = None;
+    while let Some(Incoming {
+        conn: from,
+        publish,
+    }) = rx.recv().await
+    {
+        // Packet ids only mean something on the connection they arrived on.
+        let stale = Some(from) == abandoned || from != conn.load(Ordering::Relaxed);
+        if stale && publish.qos != QoS::AtMostOnce {
+            continue;
+        }
+        let result = handle_publish(&st, &template, &publish, retries).await;
+        st.metrics.mqtt_ingress_message(result);
+        if result == "dropped" && publish.qos != QoS::AtMostOnce {
+            tracing::warn!(topic = %publish.topic, "mqtt ingress backed up, reconnecting");
+            abandoned = Some(from);
+            if let Err(e) = client.disconnect().await {
+                tracing::warn!(error = %e, "mqtt ingress disconnect failed");
+            }
+        } else if let Err(e) = client.ack(&publish).await {
+            tracing::warn!(topic = %publish.topic, error = %e, "mqtt ingress ack failed");
+        }
+    }
+}
+
+/// Returns the label used for `mqtt_ingress_messages_total`.
+async fn handle_publish(
+    st: &AppState,
+    template: &TopicTemplate,
+    p: &Publish,
+    retries: u32,
+) -> &'static str {
+    let Some(device_id) = template.device_id(&p.topic) else {
+        return "unmatched_topic";
+    };
+    submit_payload(st, device_id, &p.payload, retries).await
+}
+
+/// Submits a JSON ingest body received over MQTT, shared with the embedded broker.
+pub(crate) async fn submit_payload(
+    st: &AppState,
+    device_id: &str,
+    payload: &[u8],
+    retries: u32,
+) -> &'static str {
+    if payload.len() > st.cfg.ingest.max_payload_bytes {
+        st.metrics.ingest_rejected_total("payload_too_large");
+        return "rejected";
+    }
+
+    let Ok(body) = BodyFormat::Json.decode_ingest(payload) else {
+        st.metrics.ingest_rejected_total("invalid_body");
+        return "rejected";
+    };
+    // No way to say 503 over MQTT: hold the message briefly while the pipeline
+    // is backed up, which also slows consumption from the broker.
+    for attempt in 0..=retries {
+        match pipeline::submit(st, device_id, body.clone(), payload.len()) {
+            Ok(_) => return "accepted",
+            Err(IngestError::Invalid(_)) => return "rejected",
+            Err(_) if attempt < retries => tokio::time::sleep(Duration::from_millis(100)).await,
+            Err(_) => {}
+        }
+    }
+    "dropped"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_device_id_from_topic() {
+        let t = TopicTemplate::parse("site/+/{device_id}/telemetry").unwrap();
+        assert_eq!(t.device_id("site/aal/dev1/telemetry"), Some("dev1"));
+        assert_eq!(t.device_id("site/aal/dev1/status"), None);
+        assert_eq!(t.device_id("site/aal/dev1/telemetry/extra"), None);
+
+        let t = TopicTemplate::parse("devices/{device_id}/#").unwrap();
+        assert_eq!(t.device_id("devices/dev2/a/b"), Some("dev2"));
+    }
+
+    #[test]
+    fn rejects_templates_without_single_device_id() {
+        assert!(TopicTemplate::parse("site/+/telemetry").is_err());
+        assert!(TopicTemplate::parse("{device_id}/{device_id}").is_err());
+        assert!(TopicTemplate::parse("a/#/{device_id}").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use nix::sys::statvfs::statvfs;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::sync::watch;
 
 use crate::config::{GatewayGfg, HealthCfg};
 
@@ -8,6 +9,17 @@ pub struct Readiness {
     pub accepting: AtomicBool,
     pub disk_ok: AtomicBool,
     pub mqtt_ok: AtomicBool,
+    pub broker_ok: AtomicBool,
+    /// Clients currently connected to the embedded broker.
+    pub broker_clients: AtomicUsize,
+    /// Set once the shutdown drain period is over.
+    stopping: watch::Sender<bool>,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Readiness {
@@ -16,12 +28,25 @@ impl Readiness {
             accepting: AtomicBool::new(false),
             disk_ok: AtomicBool::new(false),
             mqtt_ok: AtomicBool::new(false),
+            broker_ok: AtomicBool::new(false),
+            broker_clients: AtomicUsize::new(0),
+            stopping: watch::channel(false).0,
         }
     }
     pub fn set_accepting(&self, v: bool) {
         self.accepting.store(v, Ordering::SeqCst);
     }
 
+    pub fn stop(&self) {
+        self.stopping.send_replace(true);
+    }
+
+    /// Resolves once `stop` has been called, for listeners that axum doesn't shut down.
+    pub async fn stopped(&self) {
+        let mut rx = self.stopping.subscribe();
+        let _ = rx.wait_for(|stopping| *stopping).await;
+    }
+
     pub fn is_ready(&self, gates: &HealthCfg) -> bool {
         let accepting = self.accepting.load(Ordering::SeqCst);
         if !accepting {
@@ -33,10 +58,14 @@ impl Readiness {
         if gates.require_mqtt && !self.mqtt_ok.load(Ordering::Relaxed) {
             return false;
         }
+        if gates.require_broker && !self.broker_ok.load(Ordering::Relaxed) {
+            return false;
+        }
         true
     }
 }
 
+#[allow(clippy::clone_on_copy, clippy::match_like_matches_macro)]
 pub fn start_readisness_probes(cfg: Arc<GatewayGfg>, ready: Arc<Readiness>) {
     let interval = std::time::Duration::from_millis(cfg.health.probe_interval_ms.unwrap_or(1000));
 
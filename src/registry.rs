@@ -0,0 +1,208 @@
+//! Metric descriptors from config (`[[metric_descriptors]]`): what a metric name
+//! means, which values are plausible, and its unit for downstream consumers.
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+use crate::app::AppState;
+use crate::config::{MetricDescriptorCfg, MetricKind};
+use crate::domain::MetricValue;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MetricDescriptor {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    pub kind: MetricKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+impl From<&MetricDescriptorCfg> for MetricDescriptor {
+    fn from(c: &MetricDescriptorCfg) -> Self {
+        Self {
+            name: c.name.clone(),
+            unit: c.unit.clone(),
+            kind: c.kind,
+            description: c.description.clone(),
+            min: c.min,
+            max: c.max,
+        }
+    }
+}
+
+impl MetricDescriptor {
+    fn in_range(&self, v: f64) -> bool {
+        self.min.is_none_or(|min| v >= min) && self.max.is_none_or(|max| v <= max)
+    }
+
+    /// Validates one value; bools and strings have no range.
+    fn check(&self, value: &MetricValue) -> Result<(), &'static str> {
+        if self.kind == MetricKind::Counter {
+            let counts = matches!(
+                value,
+                MetricValue::UInt(_) | MetricValue::Int(_) | MetricValue::Float(_)
+            );
+            if !counts || value.as_f64().is_some_and(|v| v < 0.0) {
+                return Err("invalid_counter");
+            }
+        }
+        let in_range = match value {
+            MetricValue::UInt(_) | MetricValue::Int(_) | MetricValue::Float(_) => {
+                value.as_f64().is_some_and(|v| self.in_range(v))
+            }
+            MetricValue::Array(vs) => vs.iter().all(|v| self.in_range(*v)),
+            MetricValue::Bool(_) | MetricValue::String(_) => true,
+        };
+        if !in_range {
+            return Err("out_of_range");
+        }
+        Ok(())
+    }
+}
+
+/// Descriptors by metric name. Metrics without one pass unchecked.
+pub struct MetricRegistry {
+    by_name: BTreeMap<String, MetricDescriptor>,
+}
+
+impl MetricRegistry {
+    pub fn new(cfgs: &[MetricDescriptorCfg]) -> Self {
+        Self {
+            by_name: cfgs.iter().map(|c| (c.name.clone(), c.into())).collect(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MetricDescriptor> {
+        self.by_name.get(name)
+    }
+
+    pub fn check(&self, metrics: &BTreeMap<String, MetricValue>) -> Result<(), &'static str> {
+        metrics
+            .iter()
+            .filter_map(|(name, v)| Some((self.by_name.get(name)?, v)))
+            .try_for_each(|(d, v)| d.check(v))
+    }
+
+    /// Units attached to the event: those the source sent for `metrics`, with
+    /// descriptor units taking precedence.
+    pub fn units(
+        &self,
+        metrics: &BTreeMap<String, MetricValue>,
+        mut source: BTreeMap<String, String>,
+    ) -> BTreeMap<String, String> {
+        source.retain(|name, _| metrics.contains_key(name));
+        source.extend(
+            metrics
+                .keys()
+                .filter_map(|name| Some((name.clone(), self.by_name.get(name)?.unit.clone()?))),
+        );
+        source
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/descriptors",
+    responses(
+        (status = 200, description = "All configured metric descriptors, by name", body = [MetricDescriptor]),
+    ),
+    tag = "metadata"
+)]
+pub async fn list_descriptors(State(st): State<AppState>) -> impl IntoResponse {
+    Json(st.registry.by_name.values().cloned().collect::<Vec<_>>())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/descriptors/{name}",
+    params(("name" = String, Path, description = "Metric name, e.g. `temp_c`")),
+    responses(
+        (status = 200, description = "Descriptor of the metric", body = MetricDescriptor),
+        (status = 404, description = "No descriptor for this metric"),
+    ),
+    tag = "metadata"
+)]
+pub async fn get_descriptor(
+    State(st): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match st.registry.get(&name) {
+        Some(d) => Json(d.clone()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(
+        name: &str,
+        unit: &str,
+        kind: MetricKind,
+        min: Option<f64>,
+        max: Option<f64>,
+    ) -> MetricDescriptorCfg {
+        MetricDescriptorCfg {
+            name: name.into(),
+            unit: Some(unit.into()),
+            kind,
+            description: None,
+            min,
+            max,
+        }
+    }
+
+    #[test]
+    fn checks_ranges_and_counters_and_collects_units() {
+        let reg = MetricRegistry::new(&[
+            descriptor("temp_c", "Cel", MetricKind::Gauge, Some(-40.0), Some(125.0)),
+            descriptor("restarts", "1", MetricKind::Counter, None, None),
+        ]);
+        let metrics = |pairs: &[(&str, MetricValue)]| -> BTreeMap<String, MetricValue> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect()
+        };
+
+        let ok = metrics(&[
+            ("temp_c", 21.5.into()),
+            ("restarts", MetricValue::UInt(3)),
+            ("other", (-1e9).into()),
+        ]);
+        assert_eq!(reg.check(&ok), Ok(()));
+        let source = BTreeMap::from([
+            ("temp_c".to_string(), "degC".to_string()),
+            ("other".to_string(), "m".to_string()),
+            ("absent".to_string(), "s".to_string()),
+        ]);
+        assert_eq!(
+            reg.units(&ok, source),
+            BTreeMap::from([
+                ("other".to_string(), "m".to_string()),
+                ("restarts".to_string(), "1".to_string()),
+                ("temp_c".to_string(), "Cel".to_string()),
+            ])
+        );
+
+        let hot = metrics(&[("temp_c", 130.0.into())]);
+        assert_eq!(reg.check(&hot), Err("out_of_range"));
+        let spectrum = metrics(&[("temp_c", vec![20.0, -50.0].into())]);
+        assert_eq!(reg.check(&spectrum), Err("out_of_range"));
+        let negative = metrics(&[("restarts", MetricValue::Int(-1))]);
+        assert_eq!(reg.check(&negative), Err("invalid_counter"));
+        let text = metrics(&[("restarts", MetricValue::String("many".into()))]);
+        assert_eq!(reg.check(&text), Err("invalid_counter"));
+    }
+}
@@ -0,0 +1,5 @@
+pub mod reorder;
+pub mod tracker;
+
+pub use reorder::ReorderBuffer;
+pub use tracker::{SeqOutcome, SeqTracker};
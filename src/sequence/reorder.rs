@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::SequenceCfg;
+use crate::device_map::DeviceMap;
+use crate::domain::Event;
+use crate::metrics::AppMetrics;
+
+#[derive(Default)]
+struct DevicePending {
+    next: Option<u64>,
+    held: BTreeMap<u64, (Instant, Event)>,
+    last_seen: Option<Instant>,
+}
+
+impl DevicePending {
+    /// Emit held events starting at `next` for as long as they are contiguous.
+    fn drain_contiguous(&mut self, out: &mut Vec<Event>) {
+        while let Some(next) = self.next {
+            match self.held.remove(&next) {
+                Some((_, ev)) => {
+                    self.next = Some(next.saturating_add(1));
+                    out.push(ev);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Give up waiting for anything below or at `upto`: emit it in order and move on.
+    fn skip_to(&mut self, upto: u64, out: &mut Vec<Event>) {
+        let rest = self.held.split_off(&upto.saturating_add(1));
+        let ready = std::mem::replace(&mut self.held, rest);
+        out.extend(ready.into_values().map(|(_, ev)| ev));
+        self.next = Some(upto.saturating_add(1));
+        self.drain_contiguous(out);
+    }
+
+    /// Emit everything held, in order.
+    fn release_all(&mut self, out: &mut Vec<Event>) {
+        if let Some(&upto) = self.held.keys().next_back() {
+            self.skip_to(upto, out);
+        }
+    }
+}
+
+/// Holds out-of-order events per device for a bounded time so they can be
+/// emitted in `seq` order. Events without a `seq` pass straight through.
+pub struct ReorderBuffer {
+    hold: Duration,
+    max_pending: usize,
+    devices: DeviceMap<DevicePending>,
+    metrics: Arc<AppMetrics>,
+}
+
+impl ReorderBuffer {
+    pub fn new(cfg: &SequenceCfg, max_devices: usize, metrics: Arc<AppMetrics>) -> Self {
+        Self {
+            hold: Duration::from_millis(cfg.reorder_hold_ms),
+            max_pending: cfg.reorder_max_pending,
+            devices: DeviceMap::new(max_devices),
+            metrics,
+        }
+    }
+
+    pub fn push(&mut self, ev: Event, now: Instant) -> Vec<Event> {
+        let Some(seq) = ev.seq else {
+            return vec![ev];
+        };
+        let mut out = Vec::new();
+        let (d, evicted) = self.devices.touch_evicting(&ev.device_id);
+        // A device forgotten to make room gets its held events out first.
+        if let Some(mut evicted) = evicted
+            && !evicted.held.is_empty()
+        {
+            self.metrics.reorder_released("evicted");
+            evicted.release_all(&mut out);
+        }
+        d.last_seen = Some(now);
+        match d.next {
+            // Behind the stream: nothing to wait for, pass it on.
+            Some(next) if seq < next => out.push(ev),
+            Some(next) if seq > next => {
+                d.held.insert(seq, (now + self.hold, ev));
+                if d.held.len() > self.max_pending {
+                    let lowest = *d.held.keys().next().expect("held is non-empty");
+                    self.metrics.reorder_released("overflow");
+                    d.skip_to(lowest, &mut out);
+                }
+            }
+            _ => {
+                d.next = Some(seq.saturating_add(1));
+                out.push(ev);
+                d.drain_contiguous(&mut out);
+            }
+        }
+        out
+    }
+
+    /// Release events whose hold time has expired, skipping over the gap in front of them.
+    /// Devices with nothing held that have been quiet for the hold time are forgotten.
+    pub fn flush_expired(&mut self, now: Instant) -> Vec<Event> {
+        let mut out = Vec::new();
+        for d in self.devices.values_mut() {
+            let expired = d
+                .held
+                .iter()
+                .filter(|(_, (deadline, _))| *deadline <= now)
+                .map(|(seq, _)| *seq)
+                .max();
+            if let Some(upto) = expired {
+                self.metrics.reorder_released("timeout");
+                d.skip_to(upto, &mut out);
+            }
+        }
+        let hold = self.hold;
+        self.devices
+            .retain(|d| !d.held.is_empty() || d.last_seen.is_some_and(|seen| now < seen + hold));
+        out
+    }
+
+    /// Release everything still held, in `seq` order per device.
+    pub fn drain(&mut self) -> Vec<Event> {
+        let mut out = Vec::new();
+        for d in self.devices.values_mut() {
+            d.release_all(&mut out);
+        }
+        out
+    }
+
+    pub fn tick_interval(&self) -> Duration {
+        (self.hold / 4).max(Duration::from_millis(10))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use time::OffsetDateTime;
+
+    fn ev(seq: u64) -> Event {
+        ev_from("d1", seq)
+    }
+
+    fn ev_from(device_id: &str, seq: u64) -> Event {
+        let now = OffsetDateTime::now_utc();
+        Event {
+            id: ulid::Ulid::new(),
+            device_id: device_id.into(),
+            ts: now,
+            seq: Some(seq),
+            metrics: BTreeMap::new(),
+            units: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            payload: serde_json::Value::Null,
+            received_at: now,
+            bytes: 0,
+        }
+    }
+
+    fn seqs(evs: Vec<Event>) -> Vec<u64> {
+        evs.into_iter().filter_map(|e| e.seq).collect()
+    }
+
+    #[test]
+    fn emits_in_order_once_gap_is_filled() {
+        let mut buf = ReorderBuffer::new(&SequenceCfg::default(), 100, AppMetrics::new());
+        let t0 = Instant::now();
+
+        assert_eq!(seqs(buf.push(ev(1), t0)), vec![1]);
+        assert!(buf.push(ev(3), t0).is_empty());
+        assert!(buf.push(ev(4), t0).is_empty());
+        assert_eq!(seqs(buf.push(ev(2), t0)), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn skips_gap_after_hold_expires() {
+        let cfg = SequenceCfg::default();
+        let mut buf = ReorderBuffer::new(&cfg, 100, AppMetrics::new());
+        let t0 = Instant::now();
+
+        buf.push(ev(1), t0);
+        buf.push(ev(4), t0);
+        buf.push(ev(3), t0 + Duration::from_millis(1));
+        assert!(buf.flush_expired(t0).is_empty());
+
+        let later = t0 + Duration::from_millis(cfg.reorder_hold_ms);
+        assert_eq!(seqs(buf.flush_expired(later)), vec![3, 4]);
+        assert_eq!(seqs(buf.push(ev(5), later)), vec![5]);
+    }
+
+    #[test]
+    fn caps_devices_and_forgets_idle_ones() {
+        let cfg = SequenceCfg::default();
+        let mut buf = ReorderBuffer::new(&cfg, 2, AppMetrics::new());
+        let t0 = Instant::now();
+
+        buf.push(ev_from("a", 1), t0);
+        assert!(buf.push(ev_from("a", 3), t0).is_empty());
+        buf.push(ev_from("b", 1), t0);
+        // "a" is least recently seen, so making room for "c" releases what it held.
+        assert_eq!(seqs(buf.push(ev_from("c", 1), t0)), vec![3, 1]);
+        assert_eq!(buf.devices.len(), 2);
+
+        let later = t0 + Duration::from_millis(cfg.reorder_hold_ms);
+        assert!(buf.flush_expired(later).is_empty());
+        assert!(buf.devices.is_empty());
+    }
+}
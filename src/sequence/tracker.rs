@@ -0,0 +1,287 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use time::OffsetDateTime;
+
+use crate::config::SequenceCfg;
+use crate::device_map::DeviceMap;
+
+/// How an incoming `seq` relates to what we've already seen for the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqOutcome {
+    /// First event with a `seq` for this device.
+    First,
+    /// Exactly the next expected `seq`.
+    InOrder,
+    /// Ahead of the expected `seq`; `missing` events were skipped.
+    Gap { missing: u64 },
+    /// Fills a previously detected gap (arrived out of order).
+    Late,
+    /// Already seen, or behind the stream within the dedupe window.
+    Duplicate,
+    /// Back to 0 or far behind the stream (device restarted its counter);
+    /// tracking restarts here.
+    Reset,
+}
+
+#[derive(Debug, Default)]
+struct DeviceSeq {
+    next: u64,
+    missing: BTreeSet<u64>,
+    received: u64,
+    gaps: u64,
+    missing_total: u64,
+    late: u64,
+    duplicates: u64,
+    resets: u64,
+    last_seen: Option<OffsetDateTime>,
+    /// `next` and `missing` from before the last observation was a reset, so
+    /// a rollback can restore them.
+    before_reset: Option<(u64, BTreeSet<u64>)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GapReport {
+    pub device_id: String,
+    pub next_expected: u64,
+    pub received: u64,
+    pub gaps: u64,
+    pub missing_total: u64,
+    pub late: u64,
+    pub duplicates: u64,
+    pub resets: u64,
+    /// Currently outstanding missing ranges, inclusive.
+    pub missing: Vec<(u64, u64)>,
+    pub last_seen: Option<OffsetDateTime>,
+}
+
+/// Per-device `seq` bookkeeping used for gap/reorder/duplicate detection.
+pub struct SeqTracker {
+    dedupe_window: u64,
+    max_missing: usize,
+    devices: Mutex<DeviceMap<DeviceSeq>>,
+}
+
+impl SeqTracker {
+    pub fn new(cfg: &SequenceCfg, max_devices: usize) -> Self {
+        Self {
+            dedupe_window: cfg.dedupe_window,
+            max_missing: cfg.max_missing_tracked,
+            devices: Mutex::new(DeviceMap::new(max_devices)),
+        }
+    }
+
+    pub fn observe(&self, device_id: &str, seq: u64) -> SeqOutcome {
+        let mut devices = self.devices.lock().unwrap();
+        let first = !devices.contains_key(device_id);
+        let d = devices.touch(device_id);
+        d.received += 1;
+        d.last_seen = Some(OffsetDateTime::now_utc());
+        d.before_reset = None;
+
+        if first {
+            d.next = seq.saturating_add(1);
+            return SeqOutcome::First;
+        }
+
+        if seq == d.next {
+            d.next = seq.saturating_add(1);
+            SeqOutcome::InOrder
+        } else if seq > d.next {
+            let missing = seq - d.next;
+            // Only remember the most recent holes; anything older is reported via counters.
+            let start = seq.saturating_sub(self.max_missing as u64).max(d.next);
+            d.missing.extend(start..seq);
+            while d.missing.len() > self.max_missing {
+                d.missing.pop_first();
+            }
+            d.gaps += 1;
+            d.missing_total += missing;
+            d.next = seq.saturating_add(1);
+            SeqOutcome::Gap { missing }
+        } else if d.missing.remove(&seq) {
+            d.late += 1;
+            SeqOutcome::Late
+        } else if seq != 0 && d.next - seq <= self.dedupe_window {
+            d.duplicates += 1;
+            SeqOutcome::Duplicate
+        } else {
+            d.resets += 1;
+            d.before_reset = Some((d.next, std::mem::take(&mut d.missing)));
+            d.next = seq.saturating_add(1);
+            SeqOutcome::Reset
+        }
+    }
+
+    /// Undoes `observe` for an event that never made it into the queue. If
+    /// nothing newer was seen since, the device goes back to where it was;
+    /// otherwise `seq` goes back to missing, so a retry counts as late rather
+    /// than a duplicate.
+    pub fn rollback(&self, device_id: &str, seq: u64, outcome: SeqOutcome) {
+        let mut devices = self.devices.lock().unwrap();
+        if outcome == SeqOutcome::First {
+            devices.remove(device_id);
+            return;
+        }
+        let Some(d) = devices.get_mut(device_id) else {
+            return;
+        };
+        d.received = d.received.saturating_sub(1);
+        let newest = d.next == seq.saturating_add(1);
+        match outcome {
+            SeqOutcome::Duplicate => d.duplicates = d.duplicates.saturating_sub(1),
+            SeqOutcome::Late => {
+                d.late = d.late.saturating_sub(1);
+                d.missing.insert(seq);
+            }
+            SeqOutcome::InOrder if newest => d.next = seq,
+            SeqOutcome::Gap { missing } if newest => {
+                d.gaps = d.gaps.saturating_sub(1);
+                d.missing_total = d.missing_total.saturating_sub(missing);
+                d.next = seq - missing;
+                d.missing.retain(|&m| m < d.next || m > seq);
+            }
+            SeqOutcome::Reset if newest && d.before_reset.is_some() => {
+                let (next, missing) = d.before_reset.take().expect("checked above");
+                d.resets = d.resets.saturating_sub(1);
+                d.next = next;
+                d.missing = missing;
+            }
+            _ if seq < d.next => {
+                d.missing.insert(seq);
+                while d.missing.len() > self.max_missing {
+                    d.missing.pop_first();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn report(&self, device_id: &str) -> Option<GapReport> {
+        let devices = self.devices.lock().unwrap();
+        devices.get(device_id).map(|d| to_report(device_id, d))
+    }
+
+    pub fn reports(&self) -> Vec<GapReport> {
+        let devices = self.devices.lock().unwrap();
+        let mut out: Vec<_> = devices.iter().map(|(id, d)| to_report(id, d)).collect();
+        out.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        out
+    }
+}
+
+fn to_report(device_id: &str, d: &DeviceSeq) -> GapReport {
+    GapReport {
+        device_id: device_id.to_string(),
+        next_expected: d.next,
+        received: d.received,
+        gaps: d.gaps,
+        missing_total: d.missing_total,
+        late: d.late,
+        duplicates: d.duplicates,
+        resets: d.resets,
+        missing: missing_ranges(&d.missing),
+        last_seen: d.last_seen,
+    }
+}
+
+fn missing_ranges(missing: &BTreeSet<u64>) -> Vec<(u64, u64)> {
+    let mut out: Vec<(u64, u64)> = Vec::new();
+    for &s in missing {
+        match out.last_mut() {
+            Some((_, end)) if *end + 1 == s => *end = s,
+            _ => out.push((s, s)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_gaps_late_and_duplicates() {
+        let t = SeqTracker::new(&SequenceCfg::default(), 100);
+
+        assert_eq!(t.observe("d1", 1), SeqOutcome::First);
+        assert_eq!(t.observe("d1", 2), SeqOutcome::InOrder);
+        assert_eq!(t.observe("d1", 5), SeqOutcome::Gap { missing: 2 });
+        assert_eq!(t.observe("d1", 3), SeqOutcome::Late);
+        assert_eq!(t.observe("d1", 3), SeqOutcome::Duplicate);
+        assert_eq!(t.observe("d1", 5), SeqOutcome::Duplicate);
+
+        let r = t.report("d1").unwrap();
+        assert_eq!(r.next_expected, 6);
+        assert_eq!(r.missing, vec![(4, 4)]);
+        assert_eq!((r.gaps, r.late, r.duplicates), (1, 1, 2));
+    }
+
+    #[test]
+    fn far_behind_seq_resets_stream() {
+        let cfg = SequenceCfg {
+            dedupe_window: 10,
+            ..SequenceCfg::default()
+        };
+        let t = SeqTracker::new(&cfg, 100);
+
+        t.observe("d1", 1000);
+        assert_eq!(t.observe("d1", 985), SeqOutcome::Reset);
+        assert_eq!(t.observe("d1", 986), SeqOutcome::InOrder);
+
+        // A counter back at 0 (e.g. LoRaWAN FCnt after a rejoin) is a reset even
+        // within the window.
+        t.observe("d1", 5);
+        assert_eq!(t.observe("d1", 0), SeqOutcome::Reset);
+        assert_eq!(t.observe("d1", 1), SeqOutcome::InOrder);
+    }
+
+    #[test]
+    fn device_count_is_capped() {
+        let t = SeqTracker::new(&SequenceCfg::default(), 3);
+        for i in 0..100 {
+            t.observe(&format!("spoofed-{i}"), 1);
+        }
+        let reports = t.reports();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].device_id, "spoofed-97");
+    }
+
+    #[test]
+    fn rollback_lets_a_retry_through() {
+        let t = SeqTracker::new(&SequenceCfg::default(), 100);
+        t.observe("d1", 1);
+        let outcome = t.observe("d1", 2);
+        t.rollback("d1", 2, outcome);
+        assert_eq!(t.observe("d1", 2), SeqOutcome::InOrder);
+        assert_eq!(t.observe("d1", 2), SeqOutcome::Duplicate);
+        assert_eq!(t.report("d1").unwrap().received, 3);
+
+        // Once something newer arrived, a rolled back seq waits as missing.
+        let outcome = t.observe("d1", 3);
+        t.observe("d1", 4);
+        t.rollback("d1", 3, outcome);
+        assert_eq!(t.observe("d1", 3), SeqOutcome::Late);
+    }
+
+    #[test]
+    fn rollback_undoes_gap_and_reset_counters() {
+        let t = SeqTracker::new(&SequenceCfg::default(), 100);
+        t.observe("d1", 1);
+        let outcome = t.observe("d1", 5);
+        assert_eq!(outcome, SeqOutcome::Gap { missing: 3 });
+        t.rollback("d1", 5, outcome);
+        let r = t.report("d1").unwrap();
+        assert_eq!((r.next_expected, r.gaps, r.missing_total), (2, 0, 0));
+        assert!(r.missing.is_empty());
+
+        t.observe("d1", 4);
+        let outcome = t.observe("d1", 0);
+        assert_eq!(outcome, SeqOutcome::Reset);
+        t.rollback("d1", 0, outcome);
+        let r = t.report("d1").unwrap();
+        assert_eq!((r.next_expected, r.resets), (5, 0));
+        assert_eq!(r.missing, vec![(2, 3)]);
+        assert_eq!(r.received, 2);
+    }
+}
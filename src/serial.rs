@@ -0,0 +1,225 @@
+//! Serial/UART line source. Each configured tty is read on its own thread; lines
+//! are JSON or `key=value` as for the TCP/UDP listeners, or hex-encoded frames for
+//! a configured decoder. The port is reopened after errors and unplugs.
+
+use nix::fcntl::OFlag;
+use nix::sys::termios::{self, BaudRate, ControlFlags, SetArg, SpecialCharacterIndices};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+
+use crate::app::AppState;
+use crate::config::{Parity, SerialCfg};
+use crate::ingest::types::IngestBody;
+use crate::lines::{parse_line, submit_line};
+
+pub fn baud_rate(baud: u32) -> Option<BaudRate> {
+    Some(match baud {
+        1200 => BaudRate::B1200,
+        2400 => BaudRate::B2400,
+        4800 => BaudRate::B4800,
+        9600 => BaudRate::B9600,
+        19_200 => BaudRate::B19200,
+        38_400 => BaudRate::B38400,
+        57_600 => BaudRate::B57600,
+        115_200 => BaudRate::B115200,
+        230_400 => BaudRate::B230400,
+        460_800 => BaudRate::B460800,
+        921_600 => BaudRate::B921600,
+        1_000_000 => BaudRate::B1000000,
+        2_000_000 => BaudRate::B2000000,
+        _ => return None,
+    })
+}
+
+/// Opens the tty in raw mode with the configured line settings. Reads block until
+/// data arrives and return `Ok(0)` only once the tty is hung up.
+pub fn open(cfg: &SerialCfg) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(OFlag::O_NOCTTY.bits())
+        .open(&cfg.path)?;
+
+    let mut t = termios::tcgetattr(&file)?;
+    termios::cfmakeraw(&mut t);
+    let baud = baud_rate(cfg.baud).ok_or(io::ErrorKind::InvalidInput)?;
+    termios::cfsetspeed(&mut t, baud)?;
+
+    let flags = &mut t.control_flags;
+    flags.insert(ControlFlags::CREAD | ControlFlags::CLOCAL);
+    flags.remove(ControlFlags::CSIZE | ControlFlags::PARENB | ControlFlags::PARODD);
+    flags.insert(match cfg.data_bits {
+        5 => ControlFlags::CS5,
+        6 => ControlFlags::CS6,
+        7 => ControlFlags::CS7,
+        _ => ControlFlags::CS8,
+    });
+    match cfg.parity {
+        Parity::None => {}
+        Parity::Odd => flags.insert(ControlFlags::PARENB | ControlFlags::PARODD),
+        Parity::Even => flags.insert(ControlFlags::PARENB),
+    }
+    flags.set(ControlFlags::CSTOPB, cfg.stop_bits == 2);
+
+    t.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+    t.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+    termios::tcsetattr(&file, SetArg::TCSANOW, &t)?;
+    Ok(file)
+}
+
+/// Feeds each `\n`-terminated line to `on_line` until the read fails; lines
+/// longer than `max` are skipped whole and reported as `None`.
+pub fn read_lines<R: Read>(
+    r: &mut R,
+    max: usize,
+    mut on_line: impl FnMut(Option<&[u8]>),
+) -> io::Error {
+    let mut chunk = [0u8; 1024];
+    let mut line = Vec::new();
+    let mut skipping = false;
+    loop {
+        let n = match r.read(&mut chunk) {
+            Ok(0) => return io::ErrorKind::UnexpectedEof.into(),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return e,
+        };
+        for &b in &chunk[..n] {
+            if b == b'\n' {
+                on_line((!skipping).then_some(line.as_slice()));
+                line.clear();
+                skipping = false;
+            } else if !skipping {
+                if line.len() == max {
+                    skipping = true;
+                    line.clear();
+                } else {
+                    line.push(b);
+                }
+            }
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_frame(
+    st: &AppState,
+    decoder: &str,
+    line: &str,
+) -> Result<(Option<String>, IngestBody), &'static str> {
+    let blob = parse_hex(line).ok_or("invalid_hex")?;
+    let body = st
+        .decoders
+        .decode_named(decoder, &blob)
+        .ok_or("unknown_decoder")??;
+    Ok((None, body))
+}
+
+fn ingest(st: &AppState, cfg: &SerialCfg, line: &[u8]) -> &'static str {
+    let fallback = cfg.device_id.as_deref();
+    match &cfg.decoder {
+        Some(decoder) => submit_line(st, line, fallback, |l| parse_frame(st, decoder, l)),
+        None => submit_line(st, line, fallback, parse_line),
+    }
+}
+
+/// Starts the reader thread for one port.
+pub fn spawn(cfg: SerialCfg, st: AppState) {
+    let name = format!("serial {}", cfg.path.display());
+    // Blocking tty reads stay off the async runtime.
+    if let Err(e) = std::thread::Builder::new()
+        .name(name)
+        .spawn(move || run(&cfg, &st))
+    {
+        tracing::error!(error = %e, "failed to start serial reader");
+    }
+}
+
+fn run(cfg: &SerialCfg, st: &AppState) {
+    let path = cfg.path.display().to_string();
+    let max = st.cfg.ingest.max_payload_bytes;
+    loop {
+        match open(cfg) {
+            Ok(mut port) => {
+                tracing::info!(%path, baud = cfg.baud, "serial port open");
+                st.metrics.serial_port_open(&path, true);
+                let e = read_lines(&mut port, max, |line| {
+                    let result = match line {
+                        Some(line) => ingest(st, cfg, line),
+                        None => {
+                            st.metrics.ingest_rejected_total("payload_too_large");
+                            "rejected"
+                        }
+                    };
+                    if result != "empty" {
+                        st.metrics.line_ingest("serial", result);
+                    }
+                });
+                tracing::warn!(%path, error = %e, "serial port lost");
+                st.metrics.serial_port_open(&path, false);
+            }
+            Err(e) => tracing::debug!(%path, error = %e, "serial port not available"),
+        }
+        std::thread::sleep(Duration::from_millis(cfg.reconnect_delay_ms));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::pty::openpty;
+    use std::io::Write;
+
+    #[test]
+    fn parses_hex_frames() {
+        assert_eq!(parse_hex("00fA10"), Some(vec![0x00, 0xfa, 0x10]));
+        assert_eq!(parse_hex("0"), None);
+        assert_eq!(parse_hex("zz"), None);
+    }
+
+    #[test]
+    fn reads_lines_from_pty_until_unplugged() {
+        let pty = openpty(None, None).unwrap();
+        let cfg = SerialCfg {
+            path: nix::unistd::ttyname(&pty.slave).unwrap(),
+            baud: 9600,
+            data_bits: 8,
+            parity: Parity::Even,
+            stop_bits: 1,
+            device_id: None,
+            decoder: None,
+            reconnect_delay_ms: 10,
+        };
+        let mut port = open(&cfg).unwrap();
+        drop(pty.slave);
+
+        let mut master = File::from(pty.master);
+        master
+            .write_all(b"temp_c=21.5\n{\"metrics\":{}}\r\n0123456789abcdef\npartial")
+            .unwrap();
+        // Closing the master side is what a USB unplug looks like to the reader.
+        let unplug = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            drop(master);
+        });
+
+        let mut lines = Vec::new();
+        let e = read_lines(&mut port, 12, |l| lines.push(l.map(<[u8]>::to_vec)));
+        unplug.join().unwrap();
+
+        assert_eq!(lines, vec![Some(b"temp_c=21.5".to_vec()), None, None,]);
+        // The slave side of a hung-up pty reads EIO.
+        assert_eq!(e.raw_os_error(), Some(nix::errno::Errno::EIO as i32));
+    }
+}
@@ -0,0 +1,90 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::app::AppState;
+use crate::clock::{SyncSample, unix_ms};
+use crate::ingest::types::ErrorBody;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TimeQuery {
+    /// Client transmit time (t0) in the client's own clock, ms.
+    pub t0: Option<i64>,
+}
+
+/// Result of a device's `/v1/time` exchange.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SyncReport {
+    /// `((t1 - t0) + (t2 - t3)) / 2`, ms: what the device must add to its clock.
+    pub offset_ms: i64,
+    /// `(t3 - t0) - (t2 - t1)`, ms.
+    #[serde(default)]
+    pub rtt_ms: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimeResponse {
+    /// Echo of the client's t0, if sent.
+    pub t0: Option<i64>,
+    /// Server receive time, ms since Unix epoch.
+    pub t1: i64,
+    /// Server transmit time, ms since Unix epoch.
+    pub t2: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/time",
+    params(TimeQuery),
+    responses(
+        (status = 200, description = "Server time for NTP-style offset calculation", body = TimeResponse),
+    ),
+    tag = "time"
+)]
+pub async fn time(Query(q): Query<TimeQuery>) -> impl IntoResponse {
+    let t1 = unix_ms(OffsetDateTime::now_utc());
+    let t2 = unix_ms(OffsetDateTime::now_utc());
+    Json(TimeResponse { t0: q.t0, t1, t2 })
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/time/{device_id}",
+    request_body = SyncReport,
+    params(
+        ("device_id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 204, description = "Stored; used to correct the device's `ts` when `clock.correct_drift` is on"),
+        (status = 400, description = "Offset outside the accepted `ts` window", body = ErrorBody),
+    ),
+    tag = "time"
+)]
+pub async fn report_sync(
+    State(st): State<AppState>,
+    Path(device_id): Path<String>,
+    Json(report): Json<SyncReport>,
+) -> Response {
+    let sample = SyncSample {
+        offset_ms: report.offset_ms,
+        rtt_ms: report.rtt_ms,
+        at_ms: unix_ms(OffsetDateTime::now_utc()),
+    };
+    if let Err(code) = st.clock.record_sync(&device_id, sample) {
+        let body = ErrorBody {
+            code,
+            message: format!(
+                "offset_ms must be within -{} s..={} s",
+                st.cfg.clock.max_future_s, st.cfg.clock.max_past_s
+            ),
+            index: None,
+        };
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+    st.metrics.time_sync_reported();
+    StatusCode::NO_CONTENT.into_response()
+}
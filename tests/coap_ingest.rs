@@ -0,0 +1,62 @@
+#![cfg(unix)]
+
+mod common;
+
+use coap_lite::{CoapOption, MessageClass, MessageType, Packet, RequestType, ResponseType};
+use common::{free_udp_port, retry_until_ready, spawn_gateway};
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+fn post(sock: &UdpSocket, mid: u16, device_id: &str, payload: &[u8]) -> Packet {
+    let mut req = Packet::new();
+    req.header.set_type(MessageType::Confirmable);
+    req.header.code = MessageClass::Request(RequestType::Post);
+    req.header.message_id = mid;
+    req.set_token(vec![0xab, mid as u8]);
+    req.add_option(CoapOption::UriPath, b"ingest".to_vec());
+    req.add_option(CoapOption::UriPath, device_id.as_bytes().to_vec());
+    req.payload = payload.to_vec();
+    sock.send(&req.to_bytes().unwrap()).unwrap();
+
+    let mut buf = [0u8; 1500];
+    let n = sock.recv(&mut buf).expect("no CoAP response");
+    Packet::from_bytes(&buf[..n]).unwrap()
+}
+
+#[test]
+fn confirmable_post_is_acked_with_piggybacked_response() {
+    let port = free_udp_port();
+    let _gw = spawn_gateway(&format!(
+        r#"
+[coap]
+enabled = true
+bind = "127.0.0.1:{port}"
+"#
+    ));
+
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sock.connect(("127.0.0.1", port)).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    let start = Instant::now();
+    let resp = retry_until_ready(
+        || {
+            let mid = start.elapsed().as_millis() as u16;
+            post(&sock, mid, "dev1", br#"{"metrics":{"temp_c":21.5}}"#)
+        },
+        |resp| resp.header.code == MessageClass::Response(ResponseType::ServiceUnavailable),
+    );
+    assert_eq!(resp.header.get_type(), MessageType::Acknowledgement);
+    assert_eq!(
+        resp.header.code,
+        MessageClass::Response(ResponseType::Changed)
+    );
+
+    let bad = post(&sock, 9000, "dev1", b"not json");
+    assert_eq!(bad.header.message_id, 9000);
+    assert_eq!(bad.get_token(), &[0xab, 9000u16 as u8]);
+    assert_eq!(
+        bad.header.code,
+        MessageClass::Response(ResponseType::BadRequest)
+    );
+}
@@ -0,0 +1,117 @@
+//! Shared fixture for tests that run the `gateway` binary.
+#![allow(dead_code)]
+
+use assert_cmd::prelude::*;
+use std::future::Future;
+use std::io::{BufRead, BufReader};
+use std::net::{TcpListener, UdpSocket};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A running gateway; killed on drop.
+pub struct Gateway {
+    /// HTTP address from the "listening on" line.
+    pub addr: String,
+    child: Child,
+    _dir: TempDir,
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Starts the gateway with an ephemeral HTTP port, the disk gate off, and
+/// `extra` appended to `gateway.toml`.
+pub fn spawn_gateway(extra: &str) -> Gateway {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("gateway.toml"),
+        format!(
+            r#"[http]
+bind = "127.0.0.1:0"
+
+[storage]
+min_free_bytes = 0
+{extra}"#
+        ),
+    )
+    .unwrap();
+
+    let mut child = Command::cargo_bin("gateway")
+        .unwrap()
+        .current_dir(dir.path())
+        .env_remove("RUST_LOG")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to spawn gateway");
+
+    let mut reader = BufReader::new(child.stdout.take().expect("no stdout captured"));
+    let mut line = String::new();
+    let start = Instant::now();
+    let addr = loop {
+        line.clear();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            assert!(start.elapsed() < STARTUP_TIMEOUT, "gateway did not start");
+            std::thread::sleep(Duration::from_millis(20));
+        } else if let Some(rest) = line.strip_prefix("listening on ") {
+            break rest.trim().to_string();
+        }
+    };
+    Gateway {
+        addr,
+        child,
+        _dir: dir,
+    }
+}
+
+pub fn free_tcp_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+pub fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Readiness flips only after the HTTP listener binds, and side listeners may
+/// come up a little later, so early attempts can be turned away. Repeats
+/// `attempt` while `not_ready` holds, for up to five seconds.
+pub fn retry_until_ready<T>(mut attempt: impl FnMut() -> T, not_ready: impl Fn(&T) -> bool) -> T {
+    let start = Instant::now();
+    loop {
+        let out = attempt();
+        if !not_ready(&out) || start.elapsed() > STARTUP_TIMEOUT {
+            return out;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Async version of [`retry_until_ready`].
+pub async fn retry_until_ready_async<T, F: Future<Output = T>>(
+    mut attempt: impl FnMut() -> F,
+    not_ready: impl Fn(&T) -> bool,
+) -> T {
+    let start = Instant::now();
+    loop {
+        let out = attempt().await;
+        if !not_ready(&out) || start.elapsed() > STARTUP_TIMEOUT {
+            return out;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
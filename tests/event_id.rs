@@ -0,0 +1,49 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{retry_until_ready, spawn_gateway};
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+#[test]
+fn ingest_returns_event_id_in_header_and_body() {
+    let gw = spawn_gateway(
+        r#"
+[sequence]
+drop_duplicates = true
+"#,
+    );
+
+    let client = Client::new();
+    let url = format!("http://{}/v1/ingest/dev1", gw.addr);
+    let post = |body: &'static str| {
+        client
+            .post(&url)
+            .header("content-type", "application/json")
+            .body(body)
+            .send()
+            .unwrap()
+    };
+
+    let resp = retry_until_ready(
+        || post(r#"{"seq":1,"metrics":{"temp_c":21.5}}"#),
+        |r| r.status() == 503,
+    );
+    assert_eq!(resp.status(), 202);
+    let header = resp.headers()["x-event-id"].to_str().unwrap().to_string();
+    let ack: Value = resp.json().unwrap();
+    assert_eq!(ack["status"], "accepted");
+    assert_eq!(ack["event_id"], header.as_str());
+    assert_eq!(header.len(), 26);
+
+    let dup = post(r#"{"seq":1,"metrics":{"temp_c":21.5}}"#);
+    assert_eq!(dup.status(), 202);
+    assert!(dup.headers().get("x-event-id").is_none());
+    let ack: Value = dup.json().unwrap();
+    assert_eq!(ack["status"], "duplicate");
+    assert!(ack.get("event_id").is_none());
+
+    let next = post(r#"{"seq":2,"metrics":{"temp_c":21.6}}"#);
+    assert_ne!(next.headers()["x-event-id"], header.as_str());
+}
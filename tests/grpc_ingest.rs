@@ -0,0 +1,90 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{free_tcp_port, retry_until_ready_async, spawn_gateway};
+use rust_iot_gateway::ingest::proto::{BatchReply, IngestReply, ItemStatus, Reading};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Status};
+use tonic_prost::ProstCodec;
+
+fn reading(seq: u64) -> Reading {
+    Reading {
+        seq: Some(seq),
+        ..Default::default()
+    }
+}
+
+async fn ingest(
+    grpc: &mut tonic::client::Grpc<Channel>,
+    reading: Reading,
+    device_id: Option<&str>,
+) -> Result<IngestReply, Status> {
+    let mut req = Request::new(reading);
+    if let Some(id) = device_id {
+        req.metadata_mut()
+            .insert("x-device-id", id.parse().unwrap());
+    }
+    grpc.ready().await.unwrap();
+    let path = PathAndQuery::from_static("/gateway.ingest.v1.Ingest/Ingest");
+    grpc.unary(req, path, ProstCodec::default())
+        .await
+        .map(|r| r.into_inner())
+}
+
+#[tokio::test]
+async fn unary_and_streaming_ingest() {
+    let port = free_tcp_port();
+    let _gw = spawn_gateway(&format!(
+        r#"
+[grpc]
+enabled = true
+bind = "127.0.0.1:{port}"
+
+[sequence]
+drop_duplicates = true
+"#
+    ));
+
+    let endpoint = Channel::from_shared(format!("http://127.0.0.1:{port}")).unwrap();
+    let channel = retry_until_ready_async(|| endpoint.connect(), Result::is_err)
+        .await
+        .unwrap();
+    let mut grpc = tonic::client::Grpc::new(channel);
+
+    let reply = retry_until_ready_async(
+        || {
+            let mut grpc = grpc.clone();
+            async move { ingest(&mut grpc, reading(1), Some("dev1")).await }
+        },
+        |r| matches!(r, Err(s) if s.code() == Code::Unavailable),
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply.status, ItemStatus::Accepted as i32);
+    assert_eq!(reply.event_id.len(), 26);
+
+    let err = ingest(&mut grpc, reading(2), None).await.unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+    assert_eq!(err.message(), "missing_device_id");
+
+    let stream = tonic::codegen::tokio_stream::iter(vec![reading(1), reading(2), reading(3)]);
+    let mut req = Request::new(stream);
+    req.metadata_mut()
+        .insert("x-device-id", "dev1".parse().unwrap());
+    grpc.ready().await.unwrap();
+    let path = PathAndQuery::from_static("/gateway.ingest.v1.Ingest/IngestStream");
+    let reply: BatchReply = grpc
+        .client_streaming(req, path, ProstCodec::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        (reply.accepted, reply.duplicates, reply.rejected),
+        (2, 1, 0)
+    );
+    assert_eq!(reply.results.len(), 1);
+    assert_eq!(reply.results[0].index, 0);
+    assert_eq!(reply.results[0].event_id, None);
+}
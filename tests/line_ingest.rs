@@ -0,0 +1,57 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{free_tcp_port, free_udp_port, retry_until_ready, spawn_gateway};
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+
+#[test]
+fn lines_arrive_over_tcp_and_udp_listeners() {
+    let (tcp_port, udp_port) = (free_tcp_port(), free_udp_port());
+    let gw = spawn_gateway(&format!(
+        r#"
+[lines]
+tcp_bind = "127.0.0.1:{tcp_port}"
+udp_bind = "127.0.0.1:{udp_port}"
+"#
+    ));
+    let client = Client::new();
+    let received = |device_id: &str| {
+        client
+            .get(format!("http://{}/admin/sequence/{device_id}", gw.addr))
+            .send()
+            .unwrap()
+            .json::<Value>()
+            .ok()
+            .and_then(|r| r["received"].as_u64())
+    };
+
+    let mut tcp = retry_until_ready(
+        || TcpStream::connect(("127.0.0.1", tcp_port)),
+        |r| r.is_err(),
+    )
+    .expect("tcp listener not reachable");
+    tcp.write_all(b"device_id=dev1 seq=1 temp_c=21.5\ndevice_id=dev1 seq=2 temp_c=21.6\n")
+        .unwrap();
+    assert_eq!(
+        retry_until_ready(|| received("dev1"), |r| *r != Some(2)),
+        Some(2)
+    );
+
+    let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sent = retry_until_ready(
+        || {
+            udp.send_to(
+                b"device_id=dev2 seq=1 temp_c=20.0\n",
+                ("127.0.0.1", udp_port),
+            )
+            .unwrap();
+            received("dev2")
+        },
+        |r| r.is_none(),
+    );
+    assert!(sent.is_some());
+}
@@ -0,0 +1,165 @@
+#![cfg(unix)]
+
+mod common;
+
+use bytes::BytesMut;
+use common::{free_tcp_port, retry_until_ready, spawn_gateway};
+use rumqttc::mqttbytes::v4;
+use rumqttc::{ConnectReturnCode, Packet, QoS};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+fn read_packet(stream: &mut TcpStream, buf: &mut BytesMut) -> Packet {
+    loop {
+        if let Ok(p) = v4::read(buf, 1 << 16) {
+            return p;
+        }
+        let mut chunk = [0u8; 512];
+        let n = stream.read(&mut chunk).expect("no MQTT response");
+        assert!(n > 0, "broker closed the connection");
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn connect(
+    port: u16,
+    client_id: &str,
+) -> std::io::Result<(TcpStream, BytesMut, ConnectReturnCode)> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut out = BytesMut::new();
+    v4::Connect::new(client_id).write(&mut out).unwrap();
+    stream.write_all(&out).unwrap();
+
+    let mut buf = BytesMut::new();
+    match read_packet(&mut stream, &mut buf) {
+        Packet::ConnAck(ack) => Ok((stream, buf, ack.code)),
+        other => panic!("expected CONNACK, got {other:?}"),
+    }
+}
+
+#[test]
+fn device_publishes_through_embedded_broker() {
+    let port = free_tcp_port();
+    let _gw = spawn_gateway(&format!(
+        r#"
+[broker]
+enabled = true
+bind = "127.0.0.1:{port}"
+
+[[broker.clients]]
+client_id = "dev1"
+"#
+    ));
+
+    let (mut stream, mut buf, code) = retry_until_ready(
+        || connect(port, "dev1"),
+        |r| !matches!(r, Ok((_, _, ConnectReturnCode::Success))),
+    )
+    .expect("broker not reachable");
+    assert_eq!(code, ConnectReturnCode::Success);
+
+    let mut publish = v4::Publish::new(
+        "devices/dev1/telemetry",
+        QoS::AtLeastOnce,
+        br#"{"metrics":{"temp_c":21.5}}"#.to_vec(),
+    );
+    publish.pkid = 7;
+    let mut out = BytesMut::new();
+    publish.write(&mut out).unwrap();
+    stream.write_all(&out).unwrap();
+    match read_packet(&mut stream, &mut buf) {
+        Packet::PubAck(ack) => assert_eq!(ack.pkid, 7),
+        other => panic!("expected PUBACK, got {other:?}"),
+    }
+
+    let (_, _, code) = connect(port, "stranger").unwrap();
+    assert_eq!(code, ConnectReturnCode::NotAuthorized);
+}
+
+#[test]
+fn backpressure_closes_the_connection_instead_of_acking() {
+    // The uplink probe never succeeds, so the pipeline turns every event away.
+    let port = free_tcp_port();
+    let _gw = spawn_gateway(&format!(
+        r#"
+[mqtt]
+host = "127.0.0.1"
+port = {}
+client_id = "gw-1"
+
+[health]
+require_mqtt = true
+require_disk = false
+require_broker = false
+
+[broker]
+enabled = true
+bind = "127.0.0.1:{port}"
+backpressure_retries = 0
+
+[[broker.clients]]
+client_id = "dev1"
+"#,
+        free_tcp_port()
+    ));
+
+    let (mut stream, mut buf, code) = retry_until_ready(
+        || connect(port, "dev1"),
+        |r| !matches!(r, Ok((_, _, ConnectReturnCode::Success))),
+    )
+    .expect("broker not reachable");
+    assert_eq!(code, ConnectReturnCode::Success);
+
+    let mut publish = v4::Publish::new(
+        "devices/dev1/telemetry",
+        QoS::AtLeastOnce,
+        br#"{"metrics":{"temp_c":21.5}}"#.to_vec(),
+    );
+    publish.pkid = 7;
+    let mut out = BytesMut::new();
+    publish.write(&mut out).unwrap();
+    stream.write_all(&out).unwrap();
+    let mut chunk = [0u8; 512];
+    match stream.read(&mut chunk) {
+        Ok(0) | Err(_) => {}
+        Ok(n) => {
+            buf.extend_from_slice(&chunk[..n]);
+            panic!(
+                "expected the connection to close, got {:?}",
+                v4::read(&mut buf, 1 << 16)
+            );
+        }
+    }
+}
+
+#[test]
+fn reconnect_takes_over_the_old_connection() {
+    let port = free_tcp_port();
+    let _gw = spawn_gateway(&format!(
+        r#"
+[broker]
+enabled = true
+bind = "127.0.0.1:{port}"
+
+[[broker.clients]]
+client_id = "dev1"
+"#
+    ));
+
+    let (mut old, _, code) = retry_until_ready(
+        || connect(port, "dev1"),
+        |r| !matches!(r, Ok((_, _, ConnectReturnCode::Success))),
+    )
+    .expect("broker not reachable");
+    assert_eq!(code, ConnectReturnCode::Success);
+    let (_new, _, code) = connect(port, "dev1").unwrap();
+    assert_eq!(code, ConnectReturnCode::Success);
+
+    let mut chunk = [0u8; 16];
+    let r = old.read(&mut chunk);
+    assert!(matches!(r, Ok(0)), "{r:?}");
+}
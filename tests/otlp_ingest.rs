@@ -0,0 +1,42 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{retry_until_ready, spawn_gateway};
+use reqwest::blocking::Client;
+
+const EXPORT: &str = r#"{"resourceMetrics":[{
+    "resource":{"attributes":[{"key":"device.id","value":{"stringValue":"dev1"}}]},
+    "scopeMetrics":[{"metrics":[
+        {"name":"temp_c","gauge":{"dataPoints":[
+            {"timeUnixNano":"1758628800000000000","asDouble":21.5},
+            {"timeUnixNano":"1758628801000000000","asDouble":21.6}]}}
+    ]}]}]}"#;
+
+#[test]
+fn backpressure_before_anything_is_queued_asks_for_a_retry() {
+    // Every event is larger than the in-flight budget, so each one is turned away.
+    let gw = spawn_gateway(
+        r#"
+[ingest]
+max_payload_bytes = 16
+max_inflight_bytes = 16
+"#,
+    );
+    let client = Client::new();
+    let base = format!("http://{}", gw.addr);
+    let ready = retry_until_ready(
+        || client.get(format!("{base}/readyz")).send().unwrap(),
+        |r| r.status() != 200,
+    );
+    assert_eq!(ready.status(), 200);
+
+    let resp = client
+        .post(format!("{base}/v1/metrics"))
+        .header("content-type", "application/json")
+        .body(EXPORT)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 503);
+    assert_eq!(resp.headers()["retry-after"], "1");
+}
@@ -11,6 +11,7 @@ use std::time::{Duration, Instant};
 use tempfile::tempdir;
 use wait_timeout::ChildExt; // brings .wait_timeout into scope
 
+#[allow(clippy::collapsible_if)]
 fn wait_for_status(url: &str, want: u16, timeout: Duration) -> bool {
     let client = Client::new();
     let start = Instant::now();
@@ -0,0 +1,45 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::spawn_gateway;
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+#[test]
+fn get_is_read_only_and_post_records_sync() {
+    let gw = spawn_gateway("");
+    let client = Client::new();
+    let base = format!("http://{}", gw.addr);
+
+    let resp = client
+        .get(format!(
+            "{base}/v1/time?t0=123&device_id=dev1&offset_ms=-1500"
+        ))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let time: Value = resp.json().unwrap();
+    assert_eq!(time["t0"], 123);
+    assert!(time["t2"].as_i64().unwrap() >= time["t1"].as_i64().unwrap());
+    let report = client
+        .get(format!("{base}/admin/clock/dev1"))
+        .send()
+        .unwrap();
+    assert_eq!(report.status(), 404);
+
+    let resp = client
+        .post(format!("{base}/v1/time/dev1"))
+        .json(&serde_json::json!({"offset_ms": -1500, "rtt_ms": 40}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+    let report: Value = client
+        .get(format!("{base}/admin/clock/dev1"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(report["sync"]["offset_ms"], -1500);
+    assert_eq!(report["sync"]["rtt_ms"], 40);
+}
@@ -0,0 +1,48 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{retry_until_ready, spawn_gateway};
+use serde_json::Value;
+use std::net::TcpStream;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+fn send(ws: &mut WebSocket<MaybeTlsStream<TcpStream>>, frame: &str) -> Value {
+    ws.send(Message::text(frame)).unwrap();
+    match ws.read().unwrap() {
+        Message::Text(ack) => serde_json::from_str(&ack).unwrap(),
+        other => panic!("expected text ack, got {other:?}"),
+    }
+}
+
+#[test]
+fn frames_are_acked_in_order_with_seq() {
+    let gw = spawn_gateway(
+        r#"
+[sequence]
+drop_duplicates = true
+"#,
+    );
+    let url = format!("ws://{}/v1/ingest/dev1/ws", gw.addr);
+    let (mut ws, _) = tungstenite::connect(url).unwrap();
+
+    let ack = retry_until_ready(
+        || send(&mut ws, r#"{"seq":1,"metrics":{"temp_c":21.5}}"#),
+        |ack| ack["status"] == "retry",
+    );
+    assert_eq!(ack["status"], "accepted");
+    assert_eq!(ack["seq"], 1);
+    assert_eq!(ack["event_id"].as_str().unwrap().len(), 26);
+
+    let dup = send(&mut ws, r#"{"seq":1,"metrics":{"temp_c":21.5}}"#);
+    assert_eq!(dup["status"], "duplicate");
+    assert!(dup.get("event_id").is_none());
+    assert_eq!(dup["frame"], ack["frame"].as_u64().unwrap() + 1);
+
+    let bad = send(&mut ws, "not json");
+    assert_eq!(bad["status"], "rejected");
+    assert_eq!(bad["code"], "invalid_body");
+
+    let _ = ws.close(None);
+}